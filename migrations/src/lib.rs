@@ -2,6 +2,25 @@ pub use sea_orm_migration::prelude::*;
 
 mod m20231017_000001_create_user_table;
 mod m20231109_000002_create_messages_table;
+mod m20261018_000003_create_exclusion_table;
+mod m20261018_000004_create_assignment_history_table;
+mod m20261018_000005_create_draw_table;
+mod m20261018_000006_add_draw_commitment;
+mod m20261018_000007_add_history_previous_state;
+mod m20261018_000008_add_draw_participants;
+mod m20261018_000009_add_message_archived;
+mod m20261018_000010_create_game_table;
+mod m20261018_000011_add_game_join_code;
+mod m20261018_000012_create_game_admin_table;
+mod m20261018_000013_create_game_group_table;
+mod m20261018_000014_add_game_budget;
+mod m20261018_000015_create_schedule_table;
+mod m20261018_000016_create_message_media_table;
+mod m20261018_000017_create_chat_message_table;
+mod m20261018_000018_add_user_reply_to;
+mod m20261018_000019_add_schedule_attempts;
+mod m20261018_000020_add_chat_message_header;
+mod m20261018_000021_add_draw_input;
 
 
 pub struct Migrator;
@@ -12,6 +31,25 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20231017_000001_create_user_table::Migration),
             Box::new(m20231109_000002_create_messages_table::Migration),
+            Box::new(m20261018_000003_create_exclusion_table::Migration),
+            Box::new(m20261018_000004_create_assignment_history_table::Migration),
+            Box::new(m20261018_000005_create_draw_table::Migration),
+            Box::new(m20261018_000006_add_draw_commitment::Migration),
+            Box::new(m20261018_000007_add_history_previous_state::Migration),
+            Box::new(m20261018_000008_add_draw_participants::Migration),
+            Box::new(m20261018_000009_add_message_archived::Migration),
+            Box::new(m20261018_000010_create_game_table::Migration),
+            Box::new(m20261018_000011_add_game_join_code::Migration),
+            Box::new(m20261018_000012_create_game_admin_table::Migration),
+            Box::new(m20261018_000013_create_game_group_table::Migration),
+            Box::new(m20261018_000014_add_game_budget::Migration),
+            Box::new(m20261018_000015_create_schedule_table::Migration),
+            Box::new(m20261018_000016_create_message_media_table::Migration),
+            Box::new(m20261018_000017_create_chat_message_table::Migration),
+            Box::new(m20261018_000018_add_user_reply_to::Migration),
+            Box::new(m20261018_000019_add_schedule_attempts::Migration),
+            Box::new(m20261018_000020_add_chat_message_header::Migration),
+            Box::new(m20261018_000021_add_draw_input::Migration),
         ]
     }
 }
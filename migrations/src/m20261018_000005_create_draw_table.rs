@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Draw::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Draw::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Draw::Season)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Draw::Mode)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Draw::HistorySeasons)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Draw::Seed)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Draw::AssignmentHash)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Draw::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Draw::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Draw {
+    Table,
+    Id,
+    Season,
+    Mode,
+    HistorySeasons,
+    Seed,
+    AssignmentHash,
+    CreateDate,
+}
@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Draw::Table)
+                    .add_column(
+                        ColumnDef::new(Draw::Commitment)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Draw::Revealed)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .modify_column(
+                        ColumnDef::new(Draw::AssignmentHash)
+                            .text()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Draw::Table)
+                    .drop_column(Draw::Commitment)
+                    .drop_column(Draw::Revealed)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Draw {
+    Table,
+    Commitment,
+    Revealed,
+    AssignmentHash,
+}
@@ -0,0 +1,175 @@
+use sea_orm_migration::prelude::*;
+
+/// Организатор игры, в которую переносятся все данные, созданные до появления игр
+const LEGACY_ADMIN_ID: i64 = 628456869;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Game::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Game::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Game::Name)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Game::AdminId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Game::State)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Game::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Первая игра получает id 1: к ней относятся все существующие записи
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Game::Table)
+                    .columns([Game::Name, Game::AdminId, Game::State, Game::CreateDate])
+                    .values_panic([
+                        "Тайный Санта".into(),
+                        LEGACY_ADMIN_ID.into(),
+                        "Registration".into(),
+                        Expr::current_timestamp().into(),
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ActiveGame::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ActiveGame::UserId)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ActiveGame::GameId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        for table in [
+            GameScoped::User,
+            GameScoped::Message,
+            GameScoped::Exclusion,
+            GameScoped::AssignmentHistory,
+            GameScoped::Draw,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .add_column(
+                            ColumnDef::new(GameScoped::GameId)
+                                .big_integer()
+                                .not_null()
+                                .default(1),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        // Один пользователь Telegram может участвовать в нескольких играх
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"ALTER TABLE "user" DROP CONSTRAINT user_pkey, ADD PRIMARY KEY (id, game_id)"#,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(r#"DELETE FROM "user" WHERE game_id <> 1"#)
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"ALTER TABLE "user" DROP CONSTRAINT user_pkey, ADD PRIMARY KEY (id)"#,
+            )
+            .await?;
+        for table in [
+            GameScoped::User,
+            GameScoped::Message,
+            GameScoped::Exclusion,
+            GameScoped::AssignmentHistory,
+            GameScoped::Draw,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .drop_column(GameScoped::GameId)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .drop_table(Table::drop().table(ActiveGame::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Game::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Game {
+    Table,
+    Id,
+    Name,
+    AdminId,
+    State,
+    CreateDate,
+}
+
+#[derive(Iden)]
+enum ActiveGame {
+    Table,
+    UserId,
+    GameId,
+}
+
+#[derive(Iden, Clone, Copy)]
+enum GameScoped {
+    User,
+    Message,
+    Exclusion,
+    AssignmentHistory,
+    Draw,
+    GameId,
+}
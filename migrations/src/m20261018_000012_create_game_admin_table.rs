@@ -0,0 +1,114 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(GameAdmin::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GameAdmin::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(GameAdmin::GameId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GameAdmin::UserId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GameAdmin::Role)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GameAdmin::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_game_admin_game_user")
+                    .table(GameAdmin::Table)
+                    .col(GameAdmin::GameId)
+                    .col(GameAdmin::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        // Организаторы существующих игр становятся их владельцами
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"INSERT INTO game_admin (game_id, user_id, role, create_date)
+                   SELECT id, admin_id, 'Owner', create_date FROM game"#,
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .drop_column(Game::AdminId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .add_column(
+                        ColumnDef::new(Game::AdminId)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE game SET admin_id = game_admin.user_id FROM game_admin
+                   WHERE game_admin.game_id = game.id AND game_admin.role = 'Owner'"#,
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(GameAdmin::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Game {
+    Table,
+    AdminId,
+}
+
+#[derive(Iden)]
+enum GameAdmin {
+    Table,
+    Id,
+    GameId,
+    UserId,
+    Role,
+    CreateDate,
+}
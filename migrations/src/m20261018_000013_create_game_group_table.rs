@@ -0,0 +1,112 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(GameGroup::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GameGroup::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(GameGroup::GameId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GameGroup::Name)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GameGroup::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_game_group_game_name")
+                    .table(GameGroup::Table)
+                    .col(GameGroup::GameId)
+                    .col(GameGroup::Name)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(User::GroupId).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Города, которые раньше были зашиты в бота, становятся группами первой игры,
+        // а города участников других игр — группами их игр
+        let connection = manager.get_connection();
+        connection
+            .execute_unprepared(
+                r#"INSERT INTO game_group (game_id, name, create_date)
+                   VALUES (1, 'Москва', now()), (1, 'Ижевск', now())"#,
+            )
+            .await?;
+        connection
+            .execute_unprepared(
+                r#"INSERT INTO game_group (game_id, name, create_date)
+                   SELECT DISTINCT game_id, city, now() FROM "user" WHERE city <> ''
+                   ON CONFLICT DO NOTHING"#,
+            )
+            .await?;
+        connection
+            .execute_unprepared(
+                r#"UPDATE "user" SET group_id = game_group.id FROM game_group
+                   WHERE game_group.game_id = "user".game_id AND game_group.name = "user".city"#,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::GroupId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(GameGroup::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum GameGroup {
+    Table,
+    Id,
+    GameId,
+    Name,
+    CreateDate,
+}
+
+#[derive(Iden)]
+enum User {
+    Table,
+    GroupId,
+}
@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .add_column(ColumnDef::new(Game::Budget).big_integer())
+                    .add_column(
+                        ColumnDef::new(Game::Currency)
+                            .text()
+                            .not_null()
+                            .default("рублей"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Бюджет, который раньше был зашит в шаблон, остается у первой игры
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Game::Table)
+                    .value(Game::Budget, 2000)
+                    .and_where(Expr::col(Game::Id).eq(1))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .drop_column(Game::Budget)
+                    .drop_column(Game::Currency)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Game {
+    Table,
+    Id,
+    Budget,
+    Currency,
+}
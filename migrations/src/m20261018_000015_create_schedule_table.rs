@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Schedule::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Schedule::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Schedule::GameId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Schedule::Action)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Schedule::Args)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(Schedule::RunAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Schedule::DoneAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(Schedule::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_schedule_game_action")
+                    .table(Schedule::Table)
+                    .col(Schedule::GameId)
+                    .col(Schedule::Action)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Schedule::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Schedule {
+    Table,
+    Id,
+    GameId,
+    Action,
+    Args,
+    RunAt,
+    DoneAt,
+    CreateDate,
+}
@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(MessageMedia::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MessageMedia::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(MessageMedia::GameId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MessageMedia::SantaId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MessageMedia::ChildId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MessageMedia::FromSanta)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MessageMedia::Kind)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MessageMedia::FileId)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(MessageMedia::Caption).text())
+                    .col(
+                        ColumnDef::new(MessageMedia::Archived)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(MessageMedia::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MessageMedia::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum MessageMedia {
+    Table,
+    Id,
+    GameId,
+    SantaId,
+    ChildId,
+    FromSanta,
+    Kind,
+    FileId,
+    Caption,
+    Archived,
+    CreateDate,
+}
@@ -0,0 +1,350 @@
+use std::collections::{HashMap, VecDeque};
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DbBackend, Statement};
+
+const SANTA_HEADER: &str = "<b>$santa: </b>";
+const CHILD_HEADER: &str = "<b>$child: </b>";
+
+/// Пометки вложений в старой текстовой истории: на их место переносятся сами вложения
+const MEDIA_LABELS: [&str; 8] = [
+    "[📷 Фото]",
+    "[Стикер]",
+    "[🎤 Голосовое сообщение]",
+    "[📎 Файл]",
+    "[🎬 Видео]",
+    "[🎵 Аудио]",
+    "[📹 Видеосообщение]",
+    "[GIF]",
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChatMessage::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChatMessage::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessage::GameId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessage::SantaId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessage::ChildId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessage::FromSanta)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessage::Kind)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ChatMessage::Text).text())
+                    .col(ColumnDef::new(ChatMessage::FileId).text())
+                    .col(ColumnDef::new(ChatMessage::SenderMessageId).integer())
+                    .col(ColumnDef::new(ChatMessage::RecipientMessageId).integer())
+                    .col(
+                        ColumnDef::new(ChatMessage::Archived)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessage::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_chat_message_pair")
+                    .table(ChatMessage::Table)
+                    .col(ChatMessage::GameId)
+                    .col(ChatMessage::SantaId)
+                    .col(ChatMessage::ChildId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Переписка каждой пары хранилась одной строкой вида "<b>$santa: </b>\nтекст\n<b>$child: </b>\n...",
+        // а вложения — отдельно. Строка разбивается на сообщения, пометки вложений заменяются
+        // самими вложениями, так что порядок переписки сохраняется. Время отправки известно
+        // только у вложений, текстовые сообщения получают время начала переписки.
+        let connection = manager.get_connection();
+        let mut media: HashMap<(i64, i64, i64, bool), VecDeque<(i64, bool)>> = HashMap::new();
+        for row in connection
+            .query_all(Statement::from_string(
+                DbBackend::Postgres,
+                "SELECT id, game_id, santa_id, child_id, from_santa, archived FROM message_media ORDER BY id"
+                    .to_owned(),
+            ))
+            .await?
+        {
+            media
+                .entry((
+                    row.try_get("", "game_id")?,
+                    row.try_get("", "santa_id")?,
+                    row.try_get("", "child_id")?,
+                    row.try_get("", "archived")?,
+                ))
+                .or_default()
+                .push_back((row.try_get("", "id")?, row.try_get("", "from_santa")?));
+        }
+        for row in connection
+            .query_all(Statement::from_string(
+                DbBackend::Postgres,
+                "SELECT id, game_id, santa_id, child_id, message, archived FROM message ORDER BY id"
+                    .to_owned(),
+            ))
+            .await?
+        {
+            let blob_id: i64 = row.try_get("", "id")?;
+            let message: String = row.try_get("", "message")?;
+            let pair = (
+                row.try_get("", "game_id")?,
+                row.try_get("", "santa_id")?,
+                row.try_get("", "child_id")?,
+                row.try_get("", "archived")?,
+            );
+            for (from_santa, text) in split_blob(&message) {
+                let queue = media.get_mut(&pair);
+                let is_media = MEDIA_LABELS.iter().any(|label| text.starts_with(label))
+                    && queue
+                        .as_ref()
+                        .and_then(|queue| queue.front())
+                        .is_some_and(|(_, media_from_santa)| *media_from_santa == from_santa);
+                match (is_media, queue) {
+                    (true, Some(queue)) => {
+                        let (media_id, _) = queue.pop_front().unwrap();
+                        copy_media(connection, media_id).await?;
+                    }
+                    _ => {
+                        connection
+                            .execute(Statement::from_sql_and_values(
+                                DbBackend::Postgres,
+                                r#"INSERT INTO chat_message (game_id, santa_id, child_id, from_santa, kind, text, archived, create_date)
+                                   SELECT game_id, santa_id, child_id, $1, 'text', $2, archived, create_date
+                                   FROM message WHERE id = $3"#,
+                                [from_santa.into(), text.into(), blob_id.into()],
+                            ))
+                            .await?;
+                    }
+                }
+            }
+        }
+        // Вложения без пометки в истории переносятся после нее
+        let mut rest: Vec<i64> = media
+            .into_values()
+            .flatten()
+            .map(|(media_id, _)| media_id)
+            .collect();
+        rest.sort();
+        for media_id in rest {
+            copy_media(connection, media_id).await?;
+        }
+
+        manager
+            .drop_table(Table::drop().table(Message::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(MessageMedia::Table).to_owned())
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Message::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Message::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Message::GameId)
+                            .big_integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .col(ColumnDef::new(Message::SantaId).big_integer())
+                    .col(ColumnDef::new(Message::ChildId).big_integer())
+                    .col(ColumnDef::new(Message::Message).text().not_null())
+                    .col(
+                        ColumnDef::new(Message::Archived)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(Message::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(MessageMedia::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MessageMedia::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(MessageMedia::GameId).big_integer().not_null())
+                    .col(ColumnDef::new(MessageMedia::SantaId).big_integer().not_null())
+                    .col(ColumnDef::new(MessageMedia::ChildId).big_integer().not_null())
+                    .col(ColumnDef::new(MessageMedia::FromSanta).boolean().not_null())
+                    .col(ColumnDef::new(MessageMedia::Kind).text().not_null())
+                    .col(ColumnDef::new(MessageMedia::FileId).text().not_null())
+                    .col(ColumnDef::new(MessageMedia::Caption).text())
+                    .col(
+                        ColumnDef::new(MessageMedia::Archived)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(MessageMedia::CreateDate)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        let connection = manager.get_connection();
+        connection
+            .execute_unprepared(
+                r#"INSERT INTO message (game_id, santa_id, child_id, message, archived, create_date)
+                   SELECT game_id, santa_id, child_id,
+                          string_agg(
+                              CASE WHEN from_santa THEN '<b>$santa: </b>' ELSE '<b>$child: </b>' END
+                              || E'\n' || coalesce(text, ''),
+                              E'\n' ORDER BY id),
+                          archived, min(create_date)
+                   FROM chat_message WHERE kind = 'text'
+                   GROUP BY game_id, santa_id, child_id, archived"#,
+            )
+            .await?;
+        connection
+            .execute_unprepared(
+                r#"INSERT INTO message_media (game_id, santa_id, child_id, from_santa, kind, file_id, caption, archived, create_date)
+                   SELECT game_id, santa_id, child_id, from_santa, kind, file_id, text, archived, create_date
+                   FROM chat_message WHERE kind <> 'text' ORDER BY id"#,
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ChatMessage::Table).to_owned())
+            .await
+    }
+}
+
+/// Разбивает старую историю на сообщения: (от Санты, текст)
+fn split_blob(blob: &str) -> Vec<(bool, String)> {
+    let mut messages: Vec<(bool, Vec<&str>)> = vec![];
+    for line in blob.split('\n') {
+        match line {
+            SANTA_HEADER => messages.push((true, vec![])),
+            CHILD_HEADER => messages.push((false, vec![])),
+            line => {
+                if let Some((_, lines)) = messages.last_mut() {
+                    lines.push(line);
+                }
+            }
+        }
+    }
+    messages
+        .into_iter()
+        .map(|(from_santa, lines)| (from_santa, lines.join("\n")))
+        .collect()
+}
+
+async fn copy_media<C: ConnectionTrait>(connection: &C, media_id: i64) -> Result<(), DbErr> {
+    connection
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"INSERT INTO chat_message (game_id, santa_id, child_id, from_santa, kind, text, file_id, archived, create_date)
+               SELECT game_id, santa_id, child_id, from_santa, kind, caption, file_id, archived, create_date
+               FROM message_media WHERE id = $1"#,
+            [media_id.into()],
+        ))
+        .await?;
+    Ok(())
+}
+
+#[derive(Iden)]
+enum ChatMessage {
+    Table,
+    Id,
+    GameId,
+    SantaId,
+    ChildId,
+    FromSanta,
+    Kind,
+    Text,
+    FileId,
+    SenderMessageId,
+    RecipientMessageId,
+    Archived,
+    CreateDate,
+}
+
+#[derive(Iden)]
+enum Message {
+    Table,
+    Id,
+    GameId,
+    SantaId,
+    ChildId,
+    Message,
+    Archived,
+    CreateDate,
+}
+
+#[derive(Iden)]
+enum MessageMedia {
+    Table,
+    Id,
+    GameId,
+    SantaId,
+    ChildId,
+    FromSanta,
+    Kind,
+    FileId,
+    Caption,
+    Archived,
+    CreateDate,
+}
@@ -2,30 +2,58 @@ use fmt::Debug;
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
+use std::str::FromStr;
 
+use chrono::{Datelike, Duration, FixedOffset, NaiveDateTime, TimeZone, Utc};
+use rand::distributions::Alphanumeric;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use reqwest::Url;
+use sea_orm::prelude::DateTimeWithTimeZone;
 use strum_macros::EnumString;
 use teloxide::prelude::*;
-use teloxide::types::{InputFile, KeyboardButton, KeyboardMarkup};
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, InputFile, KeyboardButton, KeyboardMarkup,
+    KeyboardRemove,
+};
 use teloxide::utils::command::BotCommands;
+use teloxide::utils::html;
 
-use crate::types::User;
-use crate::{db, SantaBot};
+use crate::distribution::{Constraints, DistributionMode, DrawInput, Pair, Participant, Seed};
+use crate::types::{
+    AdminRole, Assignment, Draw, Exclusion, Game, GameAdmin, GameState, Group, Schedule,
+    ScheduledAction, User,
+};
+use crate::{db, distribution, validation, SantaBot};
 
-pub const IZHEVSK_CITY: &str = "Ижевск";
-pub const MOSCOW_CITY: &str = "Москва";
-pub const CITY_CALLBACK_IZH: &str = "__izh_callback";
-pub const CITY_CALLBACK_MSK: &str = "__msk_callback";
-pub const CHANGE_WISH_CALLBACK: &str = "__change_wish_callback";
+/// Префиксы callback-данных кнопок, сами данные собирает [`callback_data`].
+/// У кнопки выбора группы аргумент — id группы
+pub const GROUP_CALLBACK_PREFIX: &str = "__group_callback:";
+pub const CHANGE_WISH_CALLBACK: &str = "__change_wish_callback:";
+pub const LEAVE_CALLBACK: &str = "__leave_callback:";
+pub const HISTORY_CALLBACK_PREFIX: &str = "__history_callback:";
+pub const REPLY_CALLBACK: &str = "__reply_callback:";
+pub const SEND_CALLBACK_PREFIX: &str = "__send_callback:";
 
-pub const SANTA_PATTERN: &'static str = "$santa";
-pub const CHILD_PATTERN: &'static str = "$child";
 pub const CHANGE_WISH_LIST: &str = "🎁 Обновить список желаний";
 pub const KEY_CHILD_CHAT: &str = "🏠 Перейти к беседе с подопечным";
 pub const KEY_SANTA_CHAT: &str = "🎅 Перейти к беседе с Сантой";
 pub const KEY_CHILD_CHAT_CLOSE: &str = "Закрыть чат с подопечным";
 pub const KEY_SANTA_CHAT_CLOSE: &str = "Закрыть чат с Сантой";
-pub const ADMIN_ID: i64 = 628456869;
+/// Игра, в которой оказываются пользователи, еще не выбравшие игру, и все данные до появления игр
+pub const DEFAULT_GAME_ID: i64 = 1;
+/// Сколько дней по умолчанию действует ссылка-приглашение в игру
+pub const INVITE_DAYS: i64 = 30;
+/// Дольше года ссылка-приглашение не действует
+pub const MAX_INVITE_DAYS: i64 = 365;
+/// Валюта бюджета, если организатор ее не указал
+pub const DEFAULT_CURRENCY: &str = "рублей";
+/// Часовой пояс дат в `/schedule` — московское время
+pub const SCHEDULE_UTC_OFFSET_HOURS: i32 = 3;
+/// Сколько прошлых сезонов учитывать, чтобы пары не повторялись
+pub const HISTORY_SEASONS: usize = 1;
+/// Больше прошлых сезонов учитывать незачем, а огромное число подвешивает распределение
+pub const MAX_HISTORY_SEASONS: usize = 10;
 
 #[derive(BotCommands, Clone, Default, Debug, EnumString, PartialEq)]
 pub enum State {
@@ -39,6 +67,7 @@ pub enum State {
     ChangeWishList,
     Finish,
     Distributed,
+    Left,
 }
 
 impl Display for State {
@@ -54,15 +83,99 @@ impl Display for State {
 )]
 pub enum Command {
     #[command(description = "Запуск бота")]
-    Start,
+    Start(String),
     #[command(description = "help")]
     Help,
+    #[command(
+        rename = "new_game",
+        description = "Создать игру: /new_game <название>[; <бюджет> [валюта]]"
+    )]
+    NewGame(String),
+    #[command(description = "Перейти в игру: /game <номер игры>")]
+    Game(String),
+    #[command(description = "Мои игры")]
+    Games,
+    #[command(description = "Новая ссылка-приглашение в игру: /invite [срок в днях]")]
+    Invite(String),
+    #[command(description = "Организаторы игры")]
+    Admins,
+    #[command(
+        rename = "grant_admin",
+        description = "Назначить организатора: /grant_admin <id участника>"
+    )]
+    GrantAdmin(String),
+    #[command(
+        rename = "revoke_admin",
+        description = "Снять организатора: /revoke_admin <id>"
+    )]
+    RevokeAdmin(String),
     #[command(description = "Получить список авторизованных пользователей")]
     List,
-    #[command(description = "Распредилить подопечных")]
-    Distribute,
+    #[command(
+        description = "Распредилить подопечных: /distribute [groups|pool|cycle] [кол-во прошлых сезонов, до 10]"
+    )]
+    Distribute(String),
+    #[command(
+        rename = "distribute_preview",
+        description = "Пробное распределение без сохранения: /distribute_preview [groups|pool|cycle] [N]"
+    )]
+    DistributePreview(String),
+    #[command(
+        rename = "undo_distribution",
+        description = "Отменить распределение: /undo_distribution [notify]"
+    )]
+    UndoDistribution(String),
+    #[command(
+        rename = "add_late",
+        description = "Добавить опоздавших в готовое распределение"
+    )]
+    AddLate,
+    #[command(description = "Выйти из игры")]
+    Leave,
+    #[command(description = "Удалить свое сообщение у себя и у собеседника: ответь /delete на сообщение")]
+    Delete,
+    #[command(
+        rename = "remove_user",
+        description = "Исключить участника из игры: /remove_user <id>"
+    )]
+    RemoveUser(String),
+    #[command(description = "Проверить согласованность пар Санта–подопечный")]
+    Check,
     #[command(description = "Уведомить пользователей о санте")]
     Notify,
+    #[command(description = "Опубликовать отпечаток seed следующего распределения")]
+    Commit,
+    #[command(description = "Раскрыть seed последнего распределения")]
+    Reveal,
+    #[command(
+        rename = "verify_draw",
+        description = "Проверить последнее распределение по сохраненному seed"
+    )]
+    VerifyDraw,
+    #[command(description = "Группы (города) игры")]
+    Groups,
+    #[command(
+        rename = "add_group",
+        description = "Добавить группу: /add_group <название>"
+    )]
+    AddGroup(String),
+    #[command(
+        rename = "remove_group",
+        description = "Удалить группу: /remove_group <id группы>"
+    )]
+    RemoveGroup(String),
+    #[command(
+        description = "Расписание игры: /schedule [close_registration|distribute|notify|reveal <ГГГГ-ММ-ДД ЧЧ:ММ> [аргументы]]"
+    )]
+    Schedule(String),
+    #[command(description = "Отменить запланированное действие: /unschedule <действие>")]
+    Unschedule(String),
+    #[command(description = "Запретить пару: /exclude <id санты> <id подопечного> [both]")]
+    Exclude(String),
+    #[command(description = "Список запрещенных пар")]
+    Exclusions,
+    #[command(description = "Удалить запрет: /unexclude <id запрета>")]
+    Unexclude(String),
 }
 
 #[derive(Debug)]
@@ -82,21 +195,405 @@ impl MyBot {
         Ok(())
     }
 
+    /// Создает игру, организатором которой становится ее создатель, и переводит его в нее
+    pub async fn new_game(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        args: String,
+    ) -> ResponseResult<()> {
+        let (name, budget) = match args.split_once(';') {
+            Some((name, budget)) => (name.trim(), parse_budget(budget)),
+            None => (args.trim(), Some((None, DEFAULT_CURRENCY.to_string()))),
+        };
+        let (budget, currency) = match budget {
+            Some(budget) if !name.is_empty() => budget,
+            _ => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Формат: /new_game <название игры>[; <бюджет> [валюта]]\n\
+                     Например: /new_game Офис; 1500 рублей",
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+        let game = db
+            .create_game(
+                Game {
+                    name: name.to_string(),
+                    budget,
+                    currency,
+                    join_code: Some(join_code()),
+                    join_code_expires: Some(DateTimeWithTimeZone::from(
+                        Utc::now() + Duration::days(INVITE_DAYS),
+                    )),
+                    create_date: DateTimeWithTimeZone::from(Utc::now()),
+                    ..Default::default()
+                },
+                msg.chat.id.0,
+            )
+            .await;
+        let response_msg = match game {
+            Some(game) => {
+                db.set_active_game(msg.chat.id.0, game.id).await;
+                format!(
+                    "Игра «{}» создана, ты ее организатор. Номер игры: {}\n\
+                     Бюджет подарка: {}\n\
+                     Отправь участникам ссылку-приглашение, она действует {} дней:\n{}",
+                    html::escape(&game.name),
+                    game.id,
+                    html::escape(&game.budget_text().unwrap_or("без ограничения".to_string())),
+                    INVITE_DAYS,
+                    invite_link(bot, &game).await?
+                )
+            }
+            None => "Не удалось создать игру".to_string(),
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    /// Делает игру текущей: дальнейшие сообщения и команды пользователя относятся к ней
+    pub async fn switch_game(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        args: String,
+    ) -> ResponseResult<()> {
+        let game = match args.trim().parse::<i64>() {
+            Ok(game_id) => db.get_game(game_id).await,
+            Err(_) => None,
+        };
+        let game = match game {
+            Some(game) if db.get_games(msg.chat.id.0).await.contains(&game) => game,
+            Some(_) => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Ты не участвуешь в этой игре. Присоединиться можно по ссылке-приглашению от организатора",
+                )
+                .await?;
+                return Ok(());
+            }
+            None => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Формат: /game <номер игры>, список твоих игр — /games",
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+        db.set_active_game(msg.chat.id.0, game.id).await;
+        let registered = db
+            .get_user(game.id, msg.chat.id.0)
+            .await
+            .map(|user| user.is_registered())
+            .unwrap_or(false);
+        let response_msg = match registered {
+            true => format!("Текущая игра: «{}»", html::escape(&game.name)),
+            false => format!(
+                "Текущая игра: «{}». Чтобы участвовать, нажми /start",
+                html::escape(&game.name)
+            ),
+        };
+        bot.send_message(msg.chat.id, response_msg)
+            .reply_markup(KeyboardRemove::new())
+            .await?;
+        Ok(())
+    }
+
+    /// Присоединение по ссылке-приглашению `t.me/<бот>?start=<код>`: игра становится текущей,
+    /// а в каждой игре у пользователя свои имя и список желаний
+    pub async fn join_game(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        join_code: &str,
+    ) -> ResponseResult<Option<Game>> {
+        let game = match db.find_game_by_code(join_code).await {
+            Some(game) if game.is_join_code_active() => game,
+            Some(_) => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Срок действия этой ссылки-приглашения истек. Попроси у организатора новую",
+                )
+                .await?;
+                return Ok(None);
+            }
+            None => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Ссылка-приглашение недействительна. Проверь ее или попроси у организатора новую",
+                )
+                .await?;
+                return Ok(None);
+            }
+        };
+        db.set_active_game(msg.chat.id.0, game.id).await;
+        bot.send_message(
+            msg.chat.id,
+            format!("Ты присоединяешься к игре «{}»", html::escape(&game.name)),
+        )
+        .reply_markup(KeyboardRemove::new())
+        .await?;
+        Ok(Some(game))
+    }
+
+    /// Выпускает новую ссылку-приглашение, прежняя перестает действовать
+    pub async fn invite(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let days = match args.trim() {
+            "" => INVITE_DAYS,
+            days => match days.parse::<i64>() {
+                Ok(days) if (1..=MAX_INVITE_DAYS).contains(&days) => days,
+                _ => {
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "Формат: /invite [срок действия в днях, от 1 до {}]",
+                            MAX_INVITE_DAYS
+                        ),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            },
+        };
+        let mut game = game.clone();
+        game.join_code = Some(join_code());
+        game.join_code_expires = Some(DateTimeWithTimeZone::from(
+            Utc::now() + Duration::days(days),
+        ));
+        let response_msg = match db.save_game(game).await {
+            Some(game) => format!(
+                "Ссылка-приглашение в игру «{}», действует {} дней:\n{}",
+                html::escape(&game.name),
+                days,
+                invite_link(bot, &game).await?
+            ),
+            None => "Не удалось создать ссылку-приглашение".to_string(),
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn send_games(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        let mut lines = vec![];
+        for other in db.get_games(msg.chat.id.0).await.iter() {
+            let role = match db.find_admin(other.id, msg.chat.id.0).await {
+                Some(admin) if admin.role == AdminRole::Owner => " (владелец)",
+                Some(_) => " (организатор)",
+                None => "",
+            };
+            lines.push(format!(
+                "{}{}: «{}»{}, {}",
+                if other.id == game.id { "▶ " } else { "" },
+                other.id,
+                html::escape(&other.name),
+                role,
+                match other.state {
+                    GameState::Registration => "идет регистрация",
+                    GameState::Distributed => "подопечные распределены",
+                }
+            ));
+        }
+        let response_msg = match lines.is_empty() {
+            true => "Ты пока не участвуешь ни в одной игре".to_string(),
+            false => lines.join("\n"),
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn send_admins(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        let mut lines = vec![];
+        for admin in db.get_admins(game.id).await.iter() {
+            let name = db
+                .get_user(game.id, admin.user_id)
+                .await
+                .map(|user| user.username)
+                .unwrap_or_default();
+            let role = match admin.role {
+                AdminRole::Owner => "владелец",
+                AdminRole::Admin => "организатор",
+            };
+            lines.push(format!(
+                "{} {} — {}",
+                admin.user_id,
+                html::escape(&name),
+                role
+            ));
+        }
+        bot.send_message(msg.chat.id, lines.join("\n")).await?;
+        Ok(())
+    }
+
+    /// Владелец игры назначает организатором одного из ее участников
+    pub async fn grant_admin(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let user = match args.trim().parse::<i64>() {
+            Ok(user_id) => db.get_user(game.id, user_id).await,
+            Err(_) => None,
+        };
+        let response_msg = match user {
+            None => "Формат: /grant_admin <id участника игры>".to_string(),
+            Some(user) if db.find_admin(game.id, user.id).await.is_some() => {
+                format!("{} уже организатор", html::escape(&user.username))
+            }
+            Some(user) => {
+                let granted = db
+                    .save_admin(GameAdmin {
+                        game_id: game.id,
+                        user_id: user.id,
+                        role: AdminRole::Admin,
+                        create_date: DateTimeWithTimeZone::from(Utc::now()),
+                    })
+                    .await;
+                match granted {
+                    true => {
+                        log::info!("User {} granted admin in game {}", user.id, game.id);
+                        bot.send_message(
+                            ChatId(user.chat_id),
+                            format!(
+                                "Теперь ты организатор игры «{}»",
+                                html::escape(&game.name)
+                            ),
+                        )
+                        .await?;
+                        format!("{} назначен организатором", html::escape(&user.username))
+                    }
+                    false => "Не удалось назначить организатора".to_string(),
+                }
+            }
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn revoke_admin(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let response_msg = match args.trim().parse::<i64>() {
+            Ok(user_id) if db.delete_admin(game.id, user_id).await => {
+                log::info!("User {} revoked admin in game {}", user_id, game.id);
+                "Организатор снят"
+            }
+            Ok(_) => "Такого организатора нет. Владельца игры снять нельзя",
+            Err(_) => "Формат: /revoke_admin <id>",
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    async fn set_game_state(db: &db::DatabaseHandler, game: &Game, state: GameState) {
+        let mut game = game.clone();
+        game.state = state;
+        db.save_game(game).await;
+    }
+
     pub async fn send_list_users(
         &self,
         bot: &SantaBot,
         msg: &Message,
         db: &db::DatabaseHandler,
+        game: &Game,
     ) -> ResponseResult<()> {
-        let users = db.get_all_users().await;
+        let users = db.get_all_users(game.id).await;
         bot.send_message(msg.chat.id, format!("Users: {:?}", users))
             .await?;
         Ok(())
     }
 
-    pub async fn notify(&self, bot: &SantaBot, db: &db::DatabaseHandler) -> ResponseResult<()> {
+    pub async fn check(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        let violations = validation::validate(&db.get_all_users(game.id).await);
+        let response_msg = match violations.is_empty() {
+            true => "Нарушений нет: у каждого участника один Санта и один подопечный".to_string(),
+            false => format!(
+                "Нарушения ({}):\n{}",
+                violations.len(),
+                violations
+                    .iter()
+                    .map(|violation| html::escape(&violation.to_string()))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ),
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    /// Проверка после изменения распределения: о нарушениях сообщается организатору
+    async fn report_violations(
+        bot: &SantaBot,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        let violations = validation::validate(&db.get_all_users(game.id).await);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        for violation in violations.iter() {
+            log::error!("Distribution invariant violated: {}", violation);
+        }
+        notify_admins(
+            bot,
+            db,
+            game,
+            format!(
+                "Внимание, распределение несогласовано, подробности в /check. Нарушений: {}",
+                violations.len()
+            ),
+        )
+        .await
+    }
+
+    pub async fn notify(
+        &self,
+        bot: &SantaBot,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
         let users = db
-            .get_all_users()
+            .get_all_users(game.id)
             .await
             .iter()
             .map(|user| (user.chat_id, user.clone()))
@@ -106,23 +603,7 @@ impl MyBot {
             match user.child {
                 Some(child) => {
                     let child = users.get(&child).unwrap();
-                    let response_msg =
-                        format!(include_str!("templates/state_5_notify.txt"), child.username);
-                    bot.send_message(ChatId(user.chat_id), response_msg).await?;
-                    let url_state_1 = "https://media1.giphy.com/media/v1.Y2lkPTc5MGI3NjExMzZ4cTlpMm1nMXd6NWIzZTlnZW45YXM4dTByeWc1OWQzbXhtNXI3NCZlcD12MV9pbnRlcm5hbF9naWZfYnlfaWQmY3Q9cw/63Iznk0GDRB4U8f07H/giphy.gif";
-                    bot.send_animation(
-                        ChatId(user.chat_id),
-                        InputFile::url(Url::parse(url_state_1).unwrap()),
-                    )
-                    .disable_notification(true)
-                    .await?;
-                    let response_msg = format!(
-                        include_str!("templates/state_5_notify_1.txt"),
-                        child.wish_text
-                    );
-                    bot.send_message(ChatId(user.chat_id), response_msg).await?;
-
-                    send_keyboard(&bot, ChatId(user.chat_id)).await?;
+                    send_child(bot, ChatId(user.chat_id), game, child).await?;
                     user.state = Option::from(State::Distributed);
                     db.save_user(user).await;
                 }
@@ -137,33 +618,123 @@ impl MyBot {
         bot: &SantaBot,
         msg: &Message,
         db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
     ) -> ResponseResult<()> {
-        let users: Vec<User> = db.get_all_users().await;
-        let mut izhevsk: Vec<User> = vec![];
-        let mut moscow: Vec<User> = vec![];
+        let has_groups = !db.get_groups(game.id).await.is_empty();
+        let response_msg = match Self::parse_distribution_args(&args, has_groups) {
+            Ok((mode, history_seasons)) => {
+                self.run_distribution(bot, db, game, mode, history_seasons)
+                    .await?
+            }
+            Err(response_msg) => response_msg,
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
 
-        users.into_iter().for_each(|user| match user.city.as_str() {
-            IZHEVSK_CITY => izhevsk.push(user),
-            MOSCOW_CITY => moscow.push(user),
-            _ => {}
-        });
+    /// Проводит распределение и поздравляет участников; возвращает отчет для организатора.
+    /// Общая часть `/distribute` и распределения по расписанию.
+    pub async fn run_distribution(
+        &self,
+        bot: &SantaBot,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        mode: DistributionMode,
+        history_seasons: usize,
+    ) -> ResponseResult<String> {
+        let mut ready_users: Vec<User> = db
+            .get_all_users(game.id)
+            .await
+            .into_iter()
+            .filter(|user| user.is_registered())
+            .collect();
+        let groups = db.get_groups(game.id).await;
+        let participants: Vec<Participant> = ready_users
+            .iter()
+            .map(|user| Participant::new(user, &groups))
+            .collect();
+        let constraints = Constraints::from_exclusions(&db.get_all_exclusions(game.id).await);
+        let season = Utc::now().year();
+        let history = Self::history(db, game.id, season, history_seasons).await;
+        let mut draw = match db.find_last_draw(game.id).await {
+            Some(draw) if draw.assignment_hash.is_none() => draw,
+            Some(draw) if draw.season == season && !draw.revealed => {
+                return Ok("Распределение уже проведено. Чтобы провести его заново, отмени текущее командой /undo_distribution".to_string());
+            }
+            _ => match self.commit_draw(bot, db, game).await? {
+                Some(draw) => draw,
+                None => return Ok("Не удалось провести распределение".to_string()),
+            },
+        };
+        let seed = match Self::seed(&draw) {
+            Some(seed) => seed,
+            None => return Ok("Не удалось провести распределение".to_string()),
+        };
+        let pairs = distribution::seeded_draw(&seed, &participants, mode, &constraints, &history);
+        let (pairs, honoured_seasons): (Vec<Pair>, usize) = match pairs {
+            Ok(pairs) => pairs,
+            Err(err) => return Ok(html::escape(&err.to_string())),
+        };
+        let previous_states: HashMap<i64, Option<State>> = ready_users
+            .iter()
+            .map(|user| (user.id, user.state.clone()))
+            .collect();
+        distribution::apply_pairs(&mut ready_users, &pairs);
 
-        if izhevsk.len() != moscow.len() {
-            let response_msg = format!("Кол-во людей в команде должно быть одинаковым");
-            bot.send_message(msg.chat.id, response_msg).await?;
-            return Ok(());
+        let mut response_msg = format!("Распредилил роли, кол-во человек: {}", ready_users.len());
+        if honoured_seasons < history.len() {
+            response_msg = format!(
+                "{}\nИзбежать повторов всех {} прошлых сезонов не получилось, учтено сезонов: {}",
+                response_msg,
+                history.len(),
+                honoured_seasons
+            );
         }
-        Self::_distribute(&mut izhevsk, &mut moscow);
-        moscow.reverse();
-        Self::_distribute(&mut moscow, &mut izhevsk);
-        let ready_users = [izhevsk, moscow].concat();
-        let response_msg = format!("Распредилил роли, кол-во человек: {}", ready_users.len());
-        db.save_users(ready_users).await;
-
-        bot.send_message(msg.chat.id, response_msg).await?;
+        let assignments = pairs
+            .iter()
+            .map(|pair| Assignment {
+                season,
+                santa_id: pair.santa,
+                child_id: pair.child,
+                previous_state: previous_states.get(&pair.santa).cloned().flatten(),
+                create_date: DateTimeWithTimeZone::from(Utc::now()),
+            })
+            .collect();
+        let assignment_hash = distribution::assignment_hash(&pairs);
+        response_msg = format!("{}\nХэш распределения: {}", response_msg, assignment_hash);
+        draw.season = season;
+        draw.mode = mode;
+        draw.history_seasons = history.len();
+        draw.assignment_hash = Some(assignment_hash);
+        draw.participants = participants
+            .iter()
+            .map(|participant| participant.id)
+            .collect();
+        draw.participants.sort();
+        draw.input = Some(DrawInput {
+            groups: ready_users
+                .iter()
+                .filter_map(|user| {
+                    let group_id = user
+                        .group_id
+                        .filter(|group_id| groups.iter().any(|group| group.id == *group_id))?;
+                    Some((user.id, group_id))
+                })
+                .collect(),
+            forbidden: constraints.pairs(),
+            history: history.clone(),
+        });
+        if !db
+            .save_distribution(ready_users, season, assignments, draw)
+            .await
+        {
+            return Ok("Не удалось сохранить распределение, ничего не изменено".to_string());
+        }
+        Self::set_game_state(db, game, GameState::Distributed).await;
 
         let users = db
-            .get_all_users()
+            .get_all_users(game.id)
             .await
             .iter()
             .map(|user| (user.chat_id, user.clone()))
@@ -172,19 +743,1136 @@ impl MyBot {
         for (_, user) in users.iter() {
             bot.send_message(ChatId(user.chat_id), "Хо-хо-хо! Уже сегодня мы узнаем, какому счастливчику ты сделаешь самый лучший новогодний подарок!").await?;
         }
+        Self::report_violations(bot, db, game).await?;
+        Ok(response_msg)
+    }
+
+    /// Отменяет последнее распределение одной транзакцией: очищает `santa`/`child`,
+    /// возвращает участникам состояние до распределения и удаляет пары сезона из истории.
+    /// Обязательство на seed остается в силе, и повторное распределение пройдет с тем же seed.
+    /// От подбора результата это не защищает: с другим режимом, запретами, группами или
+    /// составом участников тот же seed даст другие пары.
+    /// С аргументом `notify` участникам сообщается о перераспределении.
+    pub async fn undo_distribution(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let notify = match args.trim() {
+            "" => false,
+            "notify" => true,
+            _ => {
+                bot.send_message(msg.chat.id, "Формат: /undo_distribution [notify]")
+                    .await?;
+                return Ok(());
+            }
+        };
+        let mut draw = match db.find_last_draw(game.id).await {
+            Some(draw) if draw.assignment_hash.is_some() && !draw.revealed => draw,
+            _ => {
+                bot.send_message(msg.chat.id, "Нет распределения, которое можно отменить")
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let assignments = db.get_history(game.id, draw.season, draw.season + 1).await;
+        let previous_states: HashMap<i64, Option<State>> = assignments
+            .iter()
+            .map(|assignment| (assignment.santa_id, assignment.previous_state.clone()))
+            .collect();
+        let users: Vec<User> = db
+            .get_all_users(game.id)
+            .await
+            .into_iter()
+            .filter(|user| previous_states.contains_key(&user.id))
+            .map(|mut user| {
+                user.santa = None;
+                user.child = None;
+                user.state = previous_states
+                    .get(&user.id)
+                    .cloned()
+                    .flatten()
+                    .or(Option::from(State::Finish));
+                user
+            })
+            .collect();
+
+        let season = draw.season;
+        draw.assignment_hash = None;
+        if !db
+            .save_distribution(users.clone(), season, vec![], draw)
+            .await
+        {
+            bot.send_message(
+                msg.chat.id,
+                "Не удалось отменить распределение, ничего не изменено",
+            )
+            .await?;
+            return Ok(());
+        }
+        Self::set_game_state(db, game, GameState::Registration).await;
+
+        if notify {
+            for user in users.iter() {
+                bot.send_message(
+                    ChatId(user.chat_id),
+                    "Хо-хо-хо! Дедушка перепутал списки, поэтому Тайных Сант распределим заново. \
+                     Скоро ты узнаешь своего подопечного!",
+                )
+                .reply_markup(
+                    KeyboardMarkup::new([[KeyboardButton::new(CHANGE_WISH_LIST)]])
+                        .resize_keyboard(true),
+                )
+                .await?;
+            }
+        }
+        let response_msg = format!("Распределение отменено, участников: {}", users.len());
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    /// Добавляет в готовое распределение всех, кто зарегистрировался после него: каждый
+    /// опоздавший встает между Сантой и его подопечным. Если участники уже знают своих
+    /// подопечных, уведомляются только эти трое, остальные пары не меняются.
+    pub async fn add_late_participants(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        let draw = match db.find_last_draw(game.id).await {
+            Some(draw) if draw.assignment_hash.is_some() && !draw.revealed => draw,
+            _ => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Нет распределения, в которое можно добавить участников",
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+        let mut users: HashMap<i64, User> = db
+            .get_all_users(game.id)
+            .await
+            .into_iter()
+            .filter(|user| user.is_registered())
+            .map(|user| (user.id, user))
+            .collect();
+        let late: Vec<i64> = users
+            .values()
+            .filter(|user| user.santa.is_none() && user.child.is_none())
+            .map(|user| user.id)
+            .collect();
+        if late.is_empty() {
+            bot.send_message(msg.chat.id, "Опоздавших участников нет")
+                .await?;
+            return Ok(());
+        }
+
+        let mut pairs: Vec<Pair> = users
+            .values()
+            .filter_map(|user| {
+                user.child.map(|child| Pair {
+                    santa: user.id,
+                    child,
+                })
+            })
+            .collect();
+        let groups = db.get_groups(game.id).await;
+        let participants: Vec<Participant> = users
+            .values()
+            .map(|user| Participant::new(user, &groups))
+            .collect();
+        let exclusions = Constraints::from_exclusions(&db.get_all_exclusions(game.id).await);
+        let mut constraints = exclusions.clone();
+        for pair in Self::history(db, game.id, draw.season, draw.history_seasons)
+            .await
+            .iter()
+            .flatten()
+        {
+            constraints.forbid(pair.santa, pair.child);
+        }
+        let previous_states: HashMap<i64, Option<State>> = db
+            .get_history(game.id, draw.season, draw.season + 1)
+            .await
+            .into_iter()
+            .map(|assignment| (assignment.santa_id, assignment.previous_state))
+            .collect();
+
+        let mut rng = StdRng::from_entropy();
+        let mut added = vec![];
+        let mut failed = vec![];
+        for newcomer_id in late {
+            let newcomer = Participant::new(&users[&newcomer_id], &groups);
+            let edge = distribution::insert_participant(
+                &pairs,
+                &participants,
+                &newcomer,
+                draw.mode,
+                &constraints,
+                &mut rng,
+            )
+            .or_else(|| {
+                distribution::insert_participant(
+                    &pairs,
+                    &participants,
+                    &newcomer,
+                    draw.mode,
+                    &exclusions,
+                    &mut rng,
+                )
+            });
+            let edge = match edge {
+                Some(edge) => edge,
+                None => {
+                    failed.push(html::escape(&users[&newcomer_id].username));
+                    continue;
+                }
+            };
+
+            let mut santa = users[&edge.santa].clone();
+            let mut newcomer = users[&newcomer_id].clone();
+            let mut child = users[&edge.child].clone();
+            let notified = santa.is_notified();
+            let newcomer_state = newcomer.state.clone();
+            santa.set_child(newcomer.id);
+            newcomer.set_santa(santa.id);
+            newcomer.set_child(child.id);
+            child.set_santa(newcomer.id);
+            if notified {
+                newcomer.state = Option::from(State::Distributed);
+            }
+
+            let now = DateTimeWithTimeZone::from(Utc::now());
+            let removed = vec![Assignment {
+                season: draw.season,
+                santa_id: santa.id,
+                child_id: child.id,
+                ..Default::default()
+            }];
+            let assignments = vec![
+                Assignment {
+                    season: draw.season,
+                    santa_id: santa.id,
+                    child_id: newcomer.id,
+                    previous_state: previous_states.get(&santa.id).cloned().flatten(),
+                    create_date: now,
+                },
+                Assignment {
+                    season: draw.season,
+                    santa_id: newcomer.id,
+                    child_id: child.id,
+                    previous_state: newcomer_state,
+                    create_date: now,
+                },
+            ];
+            let amended = vec![santa.clone(), newcomer.clone(), child.clone()];
+            if !db
+                .amend_distribution(game.id, amended, draw.season, removed, assignments, vec![])
+                .await
+            {
+                failed.push(newcomer.username.clone());
+                continue;
+            }
+
+            pairs.retain(|pair| *pair != edge);
+            pairs.push(Pair {
+                santa: santa.id,
+                child: newcomer.id,
+            });
+            pairs.push(Pair {
+                santa: newcomer.id,
+                child: child.id,
+            });
+            if notified {
+                bot.send_message(
+                    ChatId(santa.chat_id),
+                    "Хо-хо-хо! К празднику присоединился еще один внучок, и теперь подарок ты готовишь ему.",
+                )
+                .await?;
+                send_child(bot, ChatId(santa.chat_id), game, &newcomer).await?;
+                send_child(bot, ChatId(newcomer.chat_id), game, &child).await?;
+                bot.send_message(
+                    ChatId(child.chat_id),
+                    "Хо-хо-хо! У тебя сменился Тайный Санта. Все, что ты напишешь Санте дальше, получит уже новый Санта.",
+                )
+                .await?;
+            }
+            added.push(newcomer.username.clone());
+            users.insert(santa.id, santa);
+            users.insert(newcomer.id, newcomer);
+            users.insert(child.id, child);
+        }
+
+        let mut response_msg = format!("Добавлено опоздавших: {}", added.len());
+        if !failed.is_empty() {
+            response_msg = format!(
+                "{}\nНе удалось добавить с учетом групп и запретов: {}",
+                response_msg,
+                failed.join(", ")
+            );
+        }
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Self::report_violations(bot, db, game).await
+    }
+
+    pub async fn ask_leave(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        user: &User,
+    ) -> ResponseResult<()> {
+        if !user.is_registered() || user.state == Option::from(State::Left) {
+            bot.send_message(msg.chat.id, "Ты пока не участвуешь в игре, нажми /start")
+                .await?;
+            return Ok(());
+        }
+        let text = match user.child {
+            Some(_) => "Точно хочешь выйти из игры? Твой подопечный перейдет к твоему Санте, а переписка будет закрыта",
+            None => "Точно хочешь выйти из игры?",
+        };
+        let inline_keyboard = InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+            "Да, выйти из игры",
+            callback_data(LEAVE_CALLBACK, user.game_id, ""),
+        )]]);
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(inline_keyboard)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_user(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let user = match args.trim().parse::<i64>() {
+            Ok(user_id) => db.get_user(game.id, user_id).await,
+            Err(_) => None,
+        };
+        let user = match user {
+            Some(user) if user.state != Option::from(State::Left) => user,
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /remove_user <id участника>")
+                    .await?;
+                return Ok(());
+            }
+        };
+        let response_msg = match self.remove_participant(bot, db, game, user.clone()).await? {
+            Some(report) => {
+                bot.send_message(
+                    ChatId(user.chat_id),
+                    "Хо-хо-хо! Организатор исключил тебя из игры Тайного Санты.",
+                )
+                .reply_markup(KeyboardRemove::new())
+                .await?;
+                report
+            }
+            None => "Не удалось исключить участника".to_string(),
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    /// Исключает участника из игры. Если подопечные уже распределены, круг чинится:
+    /// Санта выбывшего забирает его подопечного. Уведомление получает только этот Санта,
+    /// переписка с выбывшим уходит в архив. Возвращает отчет для организатора.
+    pub async fn remove_participant(
+        &self,
+        bot: &SantaBot,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        mut leaver: User,
+    ) -> ResponseResult<Option<String>> {
+        let season = match db.find_last_draw(game.id).await {
+            Some(draw) => draw.season,
+            None => Utc::now().year(),
+        };
+        let previous_states: HashMap<i64, Option<State>> = db
+            .get_history(game.id, season, season + 1)
+            .await
+            .into_iter()
+            .map(|assignment| (assignment.santa_id, assignment.previous_state))
+            .collect();
+        let now = DateTimeWithTimeZone::from(Utc::now());
+
+        let mut amended = vec![];
+        let mut removed = vec![];
+        let mut added = vec![];
+        let mut takeover = None;
+        let mut report = format!("{} выбыл из игры", html::escape(&leaver.username));
+        if let (Some(santa_id), Some(child_id)) = (leaver.santa, leaver.child) {
+            removed.push(Assignment {
+                season,
+                santa_id,
+                child_id: leaver.id,
+                ..Default::default()
+            });
+            removed.push(Assignment {
+                season,
+                santa_id: leaver.id,
+                child_id,
+                ..Default::default()
+            });
+            match (
+                db.get_user(game.id, santa_id).await,
+                db.get_user(game.id, child_id).await,
+            ) {
+                (Some(mut santa), _) if santa_id == child_id => {
+                    // Выбывший и его Санта дарили друг другу: оставшийся ждет /add_late
+                    santa.santa = None;
+                    santa.child = None;
+                    santa.state = previous_states
+                        .get(&santa.id)
+                        .cloned()
+                        .flatten()
+                        .or(Option::from(State::Finish));
+                    report = format!(
+                        "{}\n{} остался без пары, добавьте его через /add_late",
+                        report,
+                        html::escape(&santa.username)
+                    );
+                    amended.push(santa);
+                }
+                (Some(mut santa), Some(mut child)) => {
+                    santa.set_child(child.id);
+                    child.set_santa(santa.id);
+                    added.push(Assignment {
+                        season,
+                        santa_id: santa.id,
+                        child_id: child.id,
+                        previous_state: previous_states.get(&santa.id).cloned().flatten(),
+                        create_date: now,
+                    });
+                    report = format!(
+                        "{}\nТеперь {} готовит подарок для {}",
+                        report,
+                        html::escape(&santa.username),
+                        html::escape(&child.username)
+                    );
+                    amended.push(santa.clone());
+                    amended.push(child.clone());
+                    takeover = Some((santa, child));
+                }
+                _ => return Ok(None),
+            }
+        }
+        leaver.santa = None;
+        leaver.child = None;
+        leaver.state = Option::from(State::Left);
+        amended.push(leaver.clone());
+
+        if !db
+            .amend_distribution(game.id, amended, season, removed, added, vec![leaver.id])
+            .await
+        {
+            return Ok(None);
+        }
+        if let Some((santa, child)) = takeover {
+            if santa.is_notified() {
+                bot.send_message(
+                    ChatId(santa.chat_id),
+                    "Хо-хо-хо! Твой подопечный покинул игру, и теперь подарок ты готовишь его подопечному.",
+                )
+                .await?;
+                send_child(bot, ChatId(santa.chat_id), game, &child).await?;
+            }
+        }
+        Self::report_violations(bot, db, game).await?;
+        Ok(Some(report))
+    }
+
+    /// Пробное распределение в памяти: администратор видит размеры групп, выполнимость
+    /// ограничений и структуру циклов, но не имена. Ничего не сохраняется, никто не уведомляется.
+    /// Используется свой случайный seed, чтобы предпросмотр не раскрывал настоящее распределение.
+    pub async fn distribute_preview(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let has_groups = !db.get_groups(game.id).await.is_empty();
+        let (mode, history_seasons) = match Self::parse_distribution_args(&args, has_groups) {
+            Ok(args) => args,
+            Err(response_msg) => {
+                bot.send_message(msg.chat.id, response_msg).await?;
+                return Ok(());
+            }
+        };
+
+        let groups = db.get_groups(game.id).await;
+        let participants: Vec<Participant> = db
+            .get_all_users(game.id)
+            .await
+            .iter()
+            .filter(|user| user.is_registered())
+            .map(|user| Participant::new(user, &groups))
+            .collect();
+        let constraints = Constraints::from_exclusions(&db.get_all_exclusions(game.id).await);
+        let history = Self::history(db, game.id, Utc::now().year(), history_seasons).await;
+
+        let groups_text = distribution::group_sizes(&participants)
+            .iter()
+            .map(|(group, size)| format!("{} — {}", html::escape(group), size))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut response_msg = format!(
+            "Предпросмотр распределения (ничего не сохранено, никто не уведомлен)\n\
+             Режим: {}, прошлых сезонов: {}\n\
+             Участников: {}\n\
+             Группы: {}",
+            mode,
+            history.len(),
+            participants.len(),
+            groups_text
+        );
+        let seed = distribution::random_seed();
+        match distribution::seeded_draw(&seed, &participants, mode, &constraints, &history) {
+            Ok((pairs, honoured_seasons)) => {
+                let cycles_text = distribution::cycle_lengths(&pairs)
+                    .iter()
+                    .map(|length| length.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                response_msg = format!(
+                    "{}\nРаспределение возможно: да\n\
+                     Учтено прошлых сезонов: {} из {}\n\
+                     Длины циклов дарения: {}",
+                    response_msg,
+                    honoured_seasons,
+                    history.len(),
+                    cycles_text
+                );
+            }
+            Err(err) => {
+                response_msg = format!(
+                    "{}\nРаспределение возможно: нет\n{}",
+                    response_msg,
+                    html::escape(&err.to_string())
+                );
+            }
+        }
+        bot.send_message(msg.chat.id, response_msg).await?;
         Ok(())
     }
 
-    fn _distribute(first_group: &mut Vec<User>, second_group: &mut Vec<User>) {
-        for user_first in first_group.iter_mut() {
-            for user_second in second_group.iter_mut() {
-                if user_first.santa.is_none() && user_second.child.is_none() {
-                    user_first.set_santa(user_second.id);
-                    user_second.set_child(user_first.id);
-                    user_second.state = Option::from(State::Distributed);
+    /// Аргументы `/distribute`: режим и кол-во прошлых сезонов, в любом порядке.
+    /// Без режима в игре с группами Санта и подопечный из разных групп, а в игре без групп — общий пул
+    pub fn parse_distribution_args(
+        args: &str,
+        has_groups: bool,
+    ) -> Result<(DistributionMode, usize), String> {
+        let mut mode = match has_groups {
+            true => DistributionMode::default(),
+            false => DistributionMode::Pool,
+        };
+        let mut history_seasons = HISTORY_SEASONS;
+        for arg in args.split_whitespace() {
+            if let Ok(seasons) = arg.parse::<usize>() {
+                if seasons > MAX_HISTORY_SEASONS {
+                    return Err(format!(
+                        "Можно учитывать не больше {} прошлых сезонов",
+                        MAX_HISTORY_SEASONS
+                    ));
                 }
+                history_seasons = seasons;
+            } else if let Ok(arg_mode) = DistributionMode::from_str(arg) {
+                mode = arg_mode;
+            } else {
+                return Err(format!(
+                    "Неизвестный режим распределения: {}. Доступны: groups, pool, cycle",
+                    arg
+                ));
+            }
+        }
+        Ok((mode, history_seasons))
+    }
+
+    /// `/commit`: новое обязательство нельзя опубликовать, пока проведенное распределение
+    /// не раскрыто, иначе следующий `/distribute` перебросит уже выданные пары
+    pub async fn commit(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        if let Some(draw) = db.find_last_draw(game.id).await {
+            if draw.assignment_hash.is_some() && !draw.revealed {
+                bot.send_message(
+                    msg.chat.id,
+                    "Распределение уже проведено и еще не раскрыто. Новый seed можно загадать после /reveal",
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+        if self.commit_draw(bot, db, game).await?.is_none() {
+            bot.send_message(msg.chat.id, "Не удалось опубликовать обязательство")
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Генерирует seed следующего распределения и публикует всем участникам обязательство
+    /// на него (хэш seed). Сам seed раскрывается командой `/reveal` после праздника.
+    /// Если обязательство уже опубликовано, а распределения по нему еще не было (в том числе
+    /// после `/undo_distribution`), повторно публикуется оно же: seed не меняется.
+    pub async fn commit_draw(
+        &self,
+        bot: &SantaBot,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<Option<Draw>> {
+        let draw = match db.find_last_draw(game.id).await {
+            Some(draw) if draw.assignment_hash.is_none() => Some(draw),
+            _ => {
+                let seed = distribution::random_seed();
+                db.save_draw(Draw {
+                    game_id: game.id,
+                    season: Utc::now().year(),
+                    seed: hex::encode(seed),
+                    commitment: distribution::commitment(&seed),
+                    create_date: DateTimeWithTimeZone::from(Utc::now()),
+                    ..Default::default()
+                })
+                .await
+            }
+        };
+        let draw = match draw {
+            Some(draw) => draw,
+            None => return Ok(None),
+        };
+
+        let response_msg = format!(
+            "Хо-хо-хо! Дедушка загадал число, по которому распределит Тайных Сант. \
+             Вот его отпечаток:\n<code>{}</code>\n\
+             После праздника я раскрою само число, и ты сможешь проверить, что распределение было честным.",
+            draw.commitment
+        );
+        for user in db.get_all_users(game.id).await.iter() {
+            if user.is_registered() {
+                bot.send_message(ChatId(user.chat_id), response_msg.clone())
+                    .await?;
+            }
+        }
+        Ok(Some(draw))
+    }
+
+    /// Раскрывает seed последнего распределения всем его участникам вместе со всем,
+    /// что нужно для его самостоятельной проверки
+    pub async fn reveal(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        if !self.reveal_draw(bot, db, game).await? {
+            bot.send_message(msg.chat.id, "Распределений еще не было")
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Общая часть `/reveal` и раскрытия по расписанию; `false`, если распределений еще не было
+    pub async fn reveal_draw(
+        &self,
+        bot: &SantaBot,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<bool> {
+        let mut draw = match db.find_last_draw(game.id).await {
+            Some(draw) if draw.assignment_hash.is_some() => draw,
+            _ => return Ok(false),
+        };
+        let (participants, constraints, history) = Self::draw_input(db, &draw).await;
+
+        // У сохраненных входных данных группа участника — id группы, показывается ее название
+        let group_names: HashMap<String, String> = db
+            .get_groups(game.id)
+            .await
+            .into_iter()
+            .map(|group| (group.id.to_string(), group.name))
+            .collect();
+        let participants_text = participants
+            .iter()
+            .map(|participant| match participant.group.as_str() {
+                "" => participant.id.to_string(),
+                group => format!(
+                    "{} ({})",
+                    participant.id,
+                    html::escape(group_names.get(group).map_or(group, |name| name.as_str()))
+                ),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let history_text = history
+            .iter()
+            .enumerate()
+            .map(|(seasons_ago, pairs)| {
+                let season = draw.season - 1 - seasons_ago as i32;
+                format!("{}: {}", season, or_none(pairs_text(pairs)))
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let response_msg = format!(
+            "Раскрываю число, по которому распределялись Тайные Санты!\n\
+             Seed: <code>{}</code>\n\
+             Отпечаток, опубликованный до распределения: <code>{}</code>\n\
+             Хэш распределения: <code>{}</code>\n\
+             Режим: {}, учтено прошлых сезонов: {}\n\
+             Участники (id и группа): {}\n\
+             Запрещенные пары: {}\n\
+             Пары прошлых сезонов: {}\n\
+             Проверить распределение можно командой /verify_draw",
+            draw.seed,
+            draw.commitment,
+            draw.assignment_hash.clone().unwrap_or_default(),
+            draw.mode,
+            draw.history_seasons,
+            participants_text,
+            or_none(pairs_text(&constraints.pairs())),
+            match history_text.is_empty() {
+                true => "нет".to_string(),
+                false => format!("\n{}", history_text),
+            },
+        );
+
+        for participant in participants.iter() {
+            bot.send_message(ChatId(participant.id), response_msg.clone())
+                .await?;
+        }
+        draw.revealed = true;
+        db.save_draw(draw).await;
+        Ok(true)
+    }
+
+    /// Повторяет последнее распределение по раскрытому seed и сверяет хэш результата,
+    /// не раскрывая пары. До раскрытия seed проверка доступна только администратору.
+    pub async fn verify_draw(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        is_admin: bool,
+    ) -> ResponseResult<()> {
+        let draw = match db.find_last_draw(game.id).await {
+            Some(draw) if draw.assignment_hash.is_some() => draw,
+            _ => {
+                bot.send_message(msg.chat.id, "Распределений еще не было")
+                    .await?;
+                return Ok(());
+            }
+        };
+        if !draw.revealed && !is_admin {
+            bot.send_message(
+                msg.chat.id,
+                "Дедушка еще не раскрыл seed, проверить распределение можно будет после праздника",
+            )
+            .await?;
+            return Ok(());
+        }
+        let assignment_hash = draw.assignment_hash.clone().unwrap_or_default();
+        let (participants, constraints, history) = Self::draw_input(db, &draw).await;
+
+        let response_msg = match distribution::verify(
+            &draw.seed,
+            &draw.commitment,
+            &participants,
+            draw.mode,
+            &constraints,
+            &history,
+        ) {
+            Ok(pairs) if distribution::assignment_hash(&pairs) == assignment_hash => {
+                format!(
+                    "Распределение воспроизведено, хэш совпадает: {}",
+                    assignment_hash
+                )
+            }
+            Ok(pairs) => format!(
+                "Хэш не совпадает!\nСохраненный: {}\nПолученный: {}",
+                assignment_hash,
+                distribution::assignment_hash(&pairs)
+            ),
+            Err(err) => format!(
+                "Не удалось воспроизвести распределение: {}",
+                html::escape(&err.to_string())
+            ),
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    fn seed(draw: &Draw) -> Option<Seed> {
+        let seed = hex::decode(&draw.seed)
+            .ok()
+            .and_then(|seed| seed.try_into().ok());
+        if seed.is_none() {
+            log::error!("Invalid seed in draw {}", draw.id);
+        }
+        seed
+    }
+
+    /// Входные данные распределения: его участники, запреты и история прошлых сезонов.
+    /// Берутся сохраненные при распределении; для распределений, сохраненных до появления
+    /// `Draw::input`, — текущие, а до появления `Draw::participants` состав берется из истории.
+    async fn draw_input(
+        db: &db::DatabaseHandler,
+        draw: &Draw,
+    ) -> (Vec<Participant>, Constraints, Vec<Vec<Pair>>) {
+        if let Some(input) = &draw.input {
+            return (
+                input.participants(&draw.participants),
+                input.constraints(),
+                input.history.clone(),
+            );
+        }
+        let santas: Vec<i64> = match draw.participants.is_empty() {
+            true => db
+                .get_history(draw.game_id, draw.season, draw.season + 1)
+                .await
+                .iter()
+                .map(|assignment| assignment.santa_id)
+                .collect(),
+            false => draw.participants.clone(),
+        };
+        let groups = db.get_groups(draw.game_id).await;
+        let participants: Vec<Participant> = db
+            .get_all_users(draw.game_id)
+            .await
+            .iter()
+            .filter(|user| santas.contains(&user.id))
+            .map(|user| Participant::new(user, &groups))
+            .collect();
+        let constraints = Constraints::from_exclusions(&db.get_all_exclusions(draw.game_id).await);
+        let history = Self::history(db, draw.game_id, draw.season, draw.history_seasons).await;
+        (participants, constraints, history)
+    }
+
+    /// Пары прошлых сезонов, от последнего к более старым
+    async fn history(
+        db: &db::DatabaseHandler,
+        game_id: i64,
+        season: i32,
+        seasons: usize,
+    ) -> Vec<Vec<Pair>> {
+        let from_season = match i32::try_from(seasons)
+            .ok()
+            .and_then(|seasons| season.checked_sub(seasons))
+        {
+            Some(from_season) => from_season,
+            None => return vec![],
+        };
+        let assignments = db.get_history(game_id, from_season, season).await;
+        (from_season..season)
+            .rev()
+            .map(|history_season| {
+                assignments
+                    .iter()
+                    .filter(|assignment| assignment.season == history_season)
+                    .map(|assignment| Pair {
+                        santa: assignment.santa_id,
+                        child: assignment.child_id,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub async fn add_exclusion(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let parsed = match args.as_slice() {
+            [santa, child] => Some((santa, child, false)),
+            [santa, child, "both"] => Some((santa, child, true)),
+            _ => None,
+        }
+        .and_then(|(santa, child, symmetric)| {
+            Some((
+                santa.parse::<i64>().ok()?,
+                child.parse::<i64>().ok()?,
+                symmetric,
+            ))
+        })
+        .filter(|(santa_id, child_id, _)| santa_id != child_id);
+        let (santa_id, child_id, symmetric) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Формат: /exclude <id санты> <id подопечного> [both]\n\
+                     both — запрет действует в обе стороны",
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+        let (santa, child) = match (
+            db.get_user(game.id, santa_id).await,
+            db.get_user(game.id, child_id).await,
+        ) {
+            (Some(santa), Some(child)) => (santa, child),
+            _ => {
+                bot.send_message(msg.chat.id, "Участник с таким id не найден")
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        db.save_exclusion(Exclusion {
+            game_id: game.id,
+            santa_id,
+            child_id,
+            symmetric,
+            create_date: DateTimeWithTimeZone::from(Utc::now()),
+            ..Default::default()
+        })
+        .await;
+        let response_msg = format!(
+            "Запрет добавлен: {}",
+            exclusion_line(&santa.username, &child.username, symmetric)
+        );
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn send_exclusions(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        let users = db
+            .get_all_users(game.id)
+            .await
+            .into_iter()
+            .map(|user| (user.id, user.username))
+            .collect::<HashMap<i64, String>>();
+        let name = |id: i64| users.get(&id).cloned().unwrap_or(id.to_string());
+
+        let response_msg = exclusions_text(&db.get_all_exclusions(game.id).await, name);
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn remove_exclusion(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let response_msg = match args.trim().parse::<i64>() {
+            Ok(exclusion_id) if db.delete_exclusion(game.id, exclusion_id).await => "Запрет удален",
+            Ok(_) => "Запрет с таким id не найден",
+            Err(_) => "Формат: /unexclude <id запрета>",
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    /// Без аргументов показывает расписание игры, иначе планирует действие.
+    /// Время указывается по Москве; действие с прошедшим временем выполнится сразу.
+    pub async fn schedule(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        if args.trim().is_empty() {
+            return self.send_schedule(bot, msg, db, game).await;
+        }
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let parsed = match args.as_slice() {
+            [action, date, time, rest @ ..] => ScheduledAction::from_str(action)
+                .ok()
+                .zip(parse_schedule_time(date, time))
+                .map(|(action, run_at)| (action, run_at, rest.join(" "))),
+            _ => None,
+        };
+        let (action, run_at, action_args) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Формат: /schedule <действие> <ГГГГ-ММ-ДД ЧЧ:ММ> [аргументы], время московское\n\
+                     Действия: close_registration, distribute, notify, reveal\n\
+                     Например: /schedule distribute 2026-12-20 18:00 cycle",
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+        if action == ScheduledAction::Distribute {
+            if let Err(response_msg) = Self::parse_distribution_args(&action_args, true) {
+                bot.send_message(msg.chat.id, response_msg).await?;
+                return Ok(());
             }
         }
+        let saved = db
+            .save_schedule(Schedule {
+                id: 0,
+                game_id: game.id,
+                action,
+                args: action_args,
+                run_at,
+                done_at: None,
+                attempts: 0,
+                create_date: DateTimeWithTimeZone::from(Utc::now()),
+            })
+            .await;
+        let response_msg = match saved {
+            true => format!("Запланировано: {} — {}", action, format_schedule_time(&run_at)),
+            false => "Не удалось сохранить расписание".to_string(),
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    async fn send_schedule(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        let schedules = db.get_schedules(game.id).await;
+        let response_msg = if schedules.is_empty() {
+            String::from("Расписания нет")
+        } else {
+            schedules
+                .iter()
+                .map(|schedule| {
+                    format!(
+                        "{} {}{}{}",
+                        format_schedule_time(&schedule.run_at),
+                        schedule.action,
+                        match schedule.args.is_empty() {
+                            true => String::new(),
+                            false => format!(" {}", schedule.args),
+                        },
+                        match schedule.done_at {
+                            Some(_) => " (выполнено)",
+                            None => "",
+                        }
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn unschedule(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let response_msg = match ScheduledAction::from_str(args.trim()) {
+            Ok(action) if db.delete_schedule(game.id, action).await => "Действие снято с расписания",
+            Ok(_) => "Это действие не запланировано",
+            Err(_) => "Формат: /unschedule <close_registration|distribute|notify|reveal>",
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn send_groups(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+    ) -> ResponseResult<()> {
+        let groups = db.get_groups(game.id).await;
+        let response_msg = if groups.is_empty() {
+            String::from("Групп нет, все участники распределяются в одном общем пуле")
+        } else {
+            groups
+                .iter()
+                .map(|group| format!("{}: {}", group.id, html::escape(&group.name)))
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn add_group(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let name = args.trim();
+        if name.is_empty() {
+            bot.send_message(msg.chat.id, "Формат: /add_group <название группы>")
+                .await?;
+            return Ok(());
+        }
+        let group = db
+            .save_group(Group {
+                game_id: game.id,
+                name: name.to_string(),
+                create_date: DateTimeWithTimeZone::from(Utc::now()),
+                ..Default::default()
+            })
+            .await;
+        let response_msg = match group {
+            Some(group) => format!(
+                "Группа «{}» добавлена, id {}",
+                html::escape(&group.name),
+                group.id
+            ),
+            None => format!("Группа «{}» уже есть в игре", html::escape(name)),
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
+    }
+
+    pub async fn remove_group(
+        &self,
+        bot: &SantaBot,
+        msg: &Message,
+        db: &db::DatabaseHandler,
+        game: &Game,
+        args: String,
+    ) -> ResponseResult<()> {
+        let response_msg = match args.trim().parse::<i64>() {
+            Ok(group_id) if db.delete_group(game.id, group_id).await => "Группа удалена",
+            Ok(_) => "Группа с таким id не найдена",
+            Err(_) => "Формат: /remove_group <id группы>",
+        };
+        bot.send_message(msg.chat.id, response_msg).await?;
+        Ok(())
     }
 
     pub async fn send_help(&self, bot: SantaBot, msg: Message) -> ResponseResult<()> {
@@ -197,6 +1885,187 @@ impl MyBot {
     }
 }
 
+/// Сообщение всем организаторам игры
+pub async fn notify_admins(
+    bot: &SantaBot,
+    db: &db::DatabaseHandler,
+    game: &Game,
+    text: String,
+) -> ResponseResult<()> {
+    for admin in db.get_admins(game.id).await.iter() {
+        bot.send_message(ChatId(admin.user_id), text.clone())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Бюджет из аргумента `/new_game`: «1500», «1500 рублей», «50 евро»; пустой — без ограничения
+fn parse_budget(args: &str) -> Option<(Option<i64>, String)> {
+    let mut args = args.split_whitespace();
+    let budget = match args.next() {
+        Some(budget) => Some(budget.parse::<i64>().ok().filter(|budget| *budget > 0)?),
+        None => None,
+    };
+    let currency = args.collect::<Vec<&str>>().join(" ");
+    match currency.is_empty() {
+        true => Some((budget, DEFAULT_CURRENCY.to_string())),
+        false => Some((budget, currency)),
+    }
+}
+
+/// Пары «Санта → подопечный» через запятую
+fn pairs_text(pairs: &[Pair]) -> String {
+    pairs
+        .iter()
+        .map(|pair| format!("{} → {}", pair.santa, pair.child))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn or_none(text: String) -> String {
+    match text.is_empty() {
+        true => "нет".to_string(),
+        false => text,
+    }
+}
+
+/// Список запретов для `/exclusions`, `name` дает имя участника по его id
+fn exclusions_text<F>(exclusions: &[Exclusion], name: F) -> String
+where
+    F: Fn(i64) -> String,
+{
+    if exclusions.is_empty() {
+        return String::from("Запрещенных пар нет");
+    }
+    exclusions
+        .iter()
+        .map(|exclusion| {
+            format!(
+                "{}: {}",
+                exclusion.id,
+                exclusion_line(
+                    &name(exclusion.santa_id),
+                    &name(exclusion.child_id),
+                    exclusion.symmetric
+                )
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Запрет «Санта → подопечный» (↔ для запрета в обе стороны); имена экранируются,
+/// так как сообщения бота отправляются в режиме HTML
+fn exclusion_line(santa: &str, child: &str, symmetric: bool) -> String {
+    let arrow = if symmetric { "↔" } else { "→" };
+    format!("{} {} {}", html::escape(santa), arrow, html::escape(child))
+}
+
+/// Данные inline-кнопки: префикс, номер игры и аргументы. По номеру игры кнопка действует
+/// в своей игре, даже если пользователь уже переключился на другую командой `/game`.
+pub fn callback_data(prefix: &str, game_id: i64, args: impl Display) -> String {
+    format!("{}{}:{}", prefix, game_id, args)
+}
+
+/// Номер игры и аргументы из данных кнопки, собранных [`callback_data`]
+pub fn parse_callback_data<'a>(data: &'a str, prefix: &str) -> Option<(i64, &'a str)> {
+    let (game_id, args) = data.strip_prefix(prefix)?.split_once(':')?;
+    Some((game_id.parse().ok()?, args))
+}
+
+/// Регистрация в игре закрыта, если наступило запланированное время `close_registration`
+pub async fn registration_closed(db: &db::DatabaseHandler, game_id: i64) -> bool {
+    db.find_schedule(game_id, ScheduledAction::CloseRegistration)
+        .await
+        .map(|schedule| schedule.run_at <= Utc::now())
+        .unwrap_or(false)
+}
+
+fn schedule_timezone() -> FixedOffset {
+    FixedOffset::east_opt(SCHEDULE_UTC_OFFSET_HOURS * 3600).unwrap()
+}
+
+fn parse_schedule_time(date: &str, time: &str) -> Option<DateTimeWithTimeZone> {
+    let naive =
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").ok()?;
+    schedule_timezone().from_local_datetime(&naive).single()
+}
+
+pub fn format_schedule_time(time: &DateTimeWithTimeZone) -> String {
+    time.with_timezone(&schedule_timezone())
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Код для ссылки-приглашения: Telegram допускает в параметре `start` только латиницу, цифры, `_` и `-`
+fn join_code() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect()
+}
+
+async fn invite_link(bot: &SantaBot, game: &Game) -> ResponseResult<String> {
+    let me = bot.get_me().await?;
+    Ok(format!(
+        "https://t.me/{}?start={}",
+        me.username(),
+        game.join_code.clone().unwrap_or_default()
+    ))
+}
+
+/// Сообщает Санте, кто его подопечный и что тот хочет получить в подарок
+async fn send_child(
+    bot: &SantaBot,
+    chat_id: ChatId,
+    game: &Game,
+    child: &User,
+) -> ResponseResult<()> {
+    let response_msg = format!(include_str!("templates/state_5_notify.txt"), child.username);
+    bot.send_message(chat_id, response_msg).await?;
+    let url_state_1 = "https://media1.giphy.com/media/v1.Y2lkPTc5MGI3NjExMzZ4cTlpMm1nMXd6NWIzZTlnZW45YXM4dTByeWc1OWQzbXhtNXI3NCZlcD12MV9pbnRlcm5hbF9naWZfYnlfaWQmY3Q9cw/63Iznk0GDRB4U8f07H/giphy.gif";
+    bot.send_animation(chat_id, InputFile::url(Url::parse(url_state_1).unwrap()))
+        .disable_notification(true)
+        .await?;
+    let response_msg = format!(
+        include_str!("templates/state_5_notify_1.txt"),
+        child.wish_text
+    );
+    bot.send_message(chat_id, response_msg).await?;
+    if let Some(budget) = game.budget_text() {
+        let response_msg = format!(
+            include_str!("templates/state_5_notify_2.txt"),
+            html::escape(&budget)
+        );
+        bot.send_message(chat_id, response_msg).await?;
+    }
+
+    send_keyboard(bot, chat_id).await
+}
+
+/// Завершает регистрацию: участник ждет распределения и может обновить список желаний
+pub async fn send_wait_distribution(bot: &SantaBot, chat_id: ChatId) -> ResponseResult<()> {
+    let keyboard =
+        KeyboardMarkup::new([[KeyboardButton::new(CHANGE_WISH_LIST)]]).resize_keyboard(true);
+
+    let url_state_1 =
+        "https://i.pinimg.com/originals/23/c7/74/23c774529515a93be6485e75faeeed36.gif";
+    bot.send_animation(chat_id, InputFile::url(Url::parse(url_state_1).unwrap()))
+        .disable_notification(true)
+        .await?;
+    bot.send_message(chat_id, "Теперь дождись, когда остальные внучата запишутся и мы торжественно распределим Тайных Сант и подопечных.")
+        .reply_markup(keyboard)
+        .await?;
+    bot.send_message(chat_id, "Но ни в коем случае не раскрывай кто ты, иначе дух праздника и волшебства пропадет навсегда!").await?;
+    let url_state_1 =
+        "https://i.pinimg.com/originals/60/d3/03/60d303c9d5ed80378dfcbcc1923e8acc.gif";
+    bot.send_animation(chat_id, InputFile::url(Url::parse(url_state_1).unwrap()))
+        .disable_notification(true)
+        .await?;
+    Ok(())
+}
+
 async fn send_keyboard(bot: &SantaBot, chat_id: ChatId) -> ResponseResult<()> {
     let keyboard = KeyboardMarkup::new([
         [KeyboardButton::new(KEY_CHILD_CHAT)],
@@ -210,3 +2079,34 @@ async fn send_keyboard(bot: &SantaBot, chat_id: ChatId) -> ResponseResult<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exclusion(id: i64, santa_id: i64, child_id: i64, symmetric: bool) -> Exclusion {
+        Exclusion {
+            id,
+            santa_id,
+            child_id,
+            symmetric,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn renders_exclusions_without_html_tags() {
+        let names = HashMap::from([(1, "Аня".to_string()), (2, "<b>Боря</b>".to_string())]);
+        let name = |id: i64| names.get(&id).cloned().unwrap_or(id.to_string());
+        let text = exclusions_text(&[exclusion(1, 1, 2, true), exclusion(2, 2, 3, false)], name);
+        assert_eq!(
+            text,
+            "1: Аня ↔ &lt;b&gt;Боря&lt;/b&gt;\n2: &lt;b&gt;Боря&lt;/b&gt; → 3"
+        );
+    }
+
+    #[test]
+    fn renders_empty_exclusions() {
+        assert_eq!(exclusions_text(&[], |id| id.to_string()), "Запрещенных пар нет");
+    }
+}
@@ -0,0 +1,136 @@
+/// Известные валюты: начала слов и символы, по которым понятно, в какой валюте цена.
+/// Однобуквенные обозначения («р») должны совпасть целиком.
+const CURRENCIES: [&[&str]; 4] = [
+    &["₽", "р", "руб", "rub", "rur"],
+    &["$", "usd", "долл", "бакс"],
+    &["€", "eur", "евро"],
+    &["₸", "тенге", "kzt"],
+];
+
+/// Самая большая цена в валюте игры `currency`, упомянутая в списке желаний: число с валютой
+/// или множителем («2500 р», «3к», «1 500 руб», «$50», «2 тыс»). Числа без валюты (размеры,
+/// модели) не считаются, а цены в других валютах пропускаются: курс бот не знает.
+pub fn max_price(text: &str, currency: &str) -> Option<i64> {
+    let chars: Vec<char> = text.chars().collect();
+    let currency = currency.to_lowercase();
+    let game_currency = currency.split_whitespace().next().and_then(known_currency);
+    let mut prices = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() || (i > 0 && chars[i - 1].is_alphanumeric()) {
+            i += 1;
+            continue;
+        }
+        let prefix = match i > 0 && matches!(chars[i - 1], '$' | '€') {
+            true => known_currency(&chars[i - 1].to_string()),
+            false => None,
+        };
+        let (number, end) = read_number(&chars, i);
+        let (word, after_word) = read_word(&chars, end);
+        let mut multiplier = 1;
+        let mut word = word;
+        if word == "к" || word == "k" || word.starts_with("тыс") {
+            multiplier = 1000;
+            word = read_word(&chars, after_word).0;
+        }
+        let priced = match prefix.or(known_currency(&word)) {
+            Some(marked) => Some(marked) == game_currency,
+            None => multiplier > 1 || is_game_currency(&word, &currency),
+        };
+        if priced {
+            prices.push(number.saturating_mul(multiplier));
+        }
+        i = end;
+    }
+    prices.into_iter().max()
+}
+
+/// Число с разделителями разрядов: «1500», «1 500», «1.500»
+fn read_number(chars: &[char], start: usize) -> (i64, usize) {
+    let mut digits = String::new();
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i].is_ascii_digit() {
+            digits.push(chars[i]);
+            i += 1;
+        } else if matches!(chars[i], ' ' | '\u{a0}' | '.' | ',')
+            && is_thousands_group(chars, i + 1)
+        {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    (digits.parse().unwrap_or(i64::MAX), i)
+}
+
+fn is_thousands_group(chars: &[char], start: usize) -> bool {
+    chars.len() >= start + 3
+        && chars[start..start + 3].iter().all(|c| c.is_ascii_digit())
+        && !chars.get(start + 3).is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Слово после пропуска пробелов: буквы или символ валюты, в нижнем регистре
+fn read_word(chars: &[char], start: usize) -> (String, usize) {
+    let mut i = start;
+    while i < chars.len() && chars[i].is_whitespace() {
+        i += 1;
+    }
+    if i < chars.len() && matches!(chars[i], '₽' | '$' | '€' | '₸') {
+        return (chars[i].to_string(), i + 1);
+    }
+    let begin = i;
+    while i < chars.len() && chars[i].is_alphabetic() {
+        i += 1;
+    }
+    (chars[begin..i].iter().collect::<String>().to_lowercase(), i)
+}
+
+/// Номер известной валюты в [`CURRENCIES`], которую обозначает слово
+fn known_currency(word: &str) -> Option<usize> {
+    CURRENCIES.iter().position(|markers| {
+        markers.iter().any(|marker| match marker.chars().count() {
+            1 => word == *marker,
+            _ => word.starts_with(marker),
+        })
+    })
+}
+
+/// Валюта игры, которой нет среди известных, сравнивается по первым трем буквам:
+/// «баллов» совпадет с «балла» и «баллы»
+fn is_game_currency(word: &str, currency: &str) -> bool {
+    let stem: String = currency.chars().take(3).collect();
+    !word.is_empty() && !stem.is_empty() && word.starts_with(stem.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_prices_with_currency() {
+        assert_eq!(max_price("Наушники за 2500 р и книга 700 рублей", "рублей"), Some(2500));
+        assert_eq!(max_price("Кофеварка 1 500 ₽", "рублей"), Some(1500));
+        assert_eq!(max_price("Игра примерно $50", "USD"), Some(50));
+    }
+
+    #[test]
+    fn understands_thousands() {
+        assert_eq!(max_price("Что-нибудь до 3к", "рублей"), Some(3000));
+        assert_eq!(max_price("Сертификат на 5 тыс руб", "рублей"), Some(5000));
+    }
+
+    #[test]
+    fn skips_prices_in_other_currencies() {
+        assert_eq!(max_price("Сумка за 3000 руб", "EUR"), None);
+        assert_eq!(max_price("Игра за $40 или книга 700 ₽", "рублей"), Some(700));
+        assert_eq!(max_price("Плед 40 евро, свеча 15 €", "евро"), Some(40));
+        assert_eq!(max_price("Набор на 300 баллов и 2к руб", "баллов"), Some(300));
+    }
+
+    #[test]
+    fn ignores_numbers_without_currency() {
+        assert_eq!(max_price("Кроссовки 42 размера, iPhone 15", "рублей"), None);
+        assert_eq!(max_price("PS5 и 2 книги", "рублей"), None);
+    }
+}
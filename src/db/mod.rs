@@ -1,15 +1,24 @@
 use std::env;
 
-use sea_orm::ActiveValue::Set;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::ActiveValue::{NotSet, Set, Unchanged};
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, ConnectOptions, Database, DatabaseConnection, DbBackend,
-    EntityTrait, QueryFilter, Statement,
+    ActiveModelTrait, ColumnTrait, Condition, ConnectOptions, ConnectionTrait, Database,
+    DatabaseConnection, DbBackend, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    Statement, TransactionTrait,
 };
 
-use crate::bot::State;
+use crate::bot::{State, DEFAULT_GAME_ID};
 use crate::db::schema::user::Model;
-use crate::db::schema::{message, user};
-use crate::types::{Message, User};
+use crate::db::schema::{
+    active_game, assignment_history, chat_message, draw, exclusion, game, game_admin, game_group,
+    schedule, user,
+};
+use crate::types::{
+    AdminRole, Assignment, Draw, Exclusion, Game, GameAdmin, Group, Message, Schedule,
+    ScheduledAction, User,
+};
 
 pub(crate) mod schema;
 
@@ -32,64 +41,226 @@ impl DatabaseHandler {
         Self::new(env!("DATABASE_URL").to_string()).await
     }
 
-    pub async fn save_users(&self, user_dtos: Vec<User>) {
-        for user in user_dtos.into_iter() {
-            self.save_user(user).await;
+    /// Атомарно сохраняет распределение сезона: пары пользователей, историю и само распределение.
+    /// Отмена распределения сохраняется так же, с пустой историей.
+    pub async fn save_distribution(
+        &self,
+        user_dtos: Vec<User>,
+        season: i32,
+        assignments: Vec<Assignment>,
+        draw_dto: Draw,
+    ) -> bool {
+        let result: Result<(), DbErr> = async {
+            let txn = self.db.begin().await?;
+            for user in user_dtos.into_iter() {
+                Self::upsert_user(&txn, user).await?;
+            }
+            Self::replace_history(&txn, draw_dto.game_id, season, assignments).await?;
+            Self::upsert_draw(&txn, draw_dto).await?;
+            txn.commit().await
         }
+        .await;
+        result
+            .map_err(|x| log::error!("Error accessing the database: {:?}", x))
+            .is_ok()
     }
 
     pub async fn save_message(&self, message_dto: Message) {
-        if let Some(message_db) = self
-            .find_message(message_dto.santa_id, message_dto.child_id)
-            .await
-        {
-            let mut message: message::ActiveModel = message_db.into();
-            message.message = Set(format!(
-                "{}\n{}",
-                message.message.unwrap(),
-                message_dto.message
-            ));
-            if let Err(x) = message.update(&self.db).await {
+        let new_message = chat_message::ActiveModel {
+            id: Default::default(),
+            game_id: Set(message_dto.game_id),
+            santa_id: Set(message_dto.santa_id),
+            child_id: Set(message_dto.child_id),
+            from_santa: Set(message_dto.from_santa),
+            kind: Set(message_dto.kind.to_string()),
+            text: Set(message_dto.text),
+            file_id: Set(message_dto.file_id),
+            sender_message_id: Set(message_dto.sender_message_id),
+            recipient_message_id: Set(message_dto.recipient_message_id),
+            header_message_id: Set(message_dto.header_message_id),
+            archived: Set(false),
+            create_date: Set(message_dto.create_date),
+        };
+        if let Err(x) = new_message.insert(&self.db).await {
+            log::error!("Error accessing the database: {:?}", x);
+        }
+    }
+
+    /// Сообщение переписки по его id в чате пользователя, в любой из его игр:
+    /// в своем чате у сообщения id отправителя, в чужом — id пересланной копии
+    pub async fn find_chat_message_in_chat(
+        &self,
+        user_id: i64,
+        message_id: i32,
+    ) -> Option<Message> {
+        chat_message::Entity::find()
+            .filter(chat_message::Column::Archived.eq(false))
+            .filter(
+                Condition::any()
+                    .add(
+                        Condition::all()
+                            .add(chat_message::Column::SantaId.eq(user_id))
+                            .add(chat_side(true, message_id)),
+                    )
+                    .add(
+                        Condition::all()
+                            .add(chat_message::Column::ChildId.eq(user_id))
+                            .add(chat_side(false, message_id)),
+                    ),
+            )
+            .one(&self.db)
+            .await
+            .unwrap_or_else(|x| {
                 log::error!("Error accessing the database: {:?}", x);
+                None
+            })
+            .and_then(Message::to_message)
+    }
+
+    /// Запоминает сообщение, на которое пользователь отвечает кнопкой «Ответить».
+    /// Начатый ответ один на все игры пользователя, прежний отменяется
+    pub async fn set_reply_to(&self, user_id: i64, game_id: i64, message_id: i32) {
+        let result = self
+            .db
+            .transaction::<_, (), DbErr>(|txn| {
+                Box::pin(async move {
+                    Self::clear_reply_to(txn, user_id).await?;
+                    user::Entity::update_many()
+                        .col_expr(user::Column::ReplyToMessageId, Expr::value(message_id))
+                        .filter(user::Column::Id.eq(user_id))
+                        .filter(user::Column::GameId.eq(game_id))
+                        .exec(txn)
+                        .await?;
+                    Ok(())
+                })
+            })
+            .await;
+        if let Err(x) = result {
+            log::error!("Error accessing the database: {:?}", x);
+        }
+    }
+
+    /// Забирает начатый пользователем ответ кнопкой «Ответить», если он есть
+    pub async fn take_reply_to(&self, user_id: i64) -> Option<i32> {
+        let result = async {
+            let reply_to = user::Entity::find()
+                .filter(user::Column::Id.eq(user_id))
+                .filter(user::Column::ReplyToMessageId.is_not_null())
+                .one(&self.db)
+                .await?
+                .and_then(|user| user.reply_to_message_id);
+            if reply_to.is_some() {
+                Self::clear_reply_to(&self.db, user_id).await?;
             }
-        } else {
-            let new_message = message::ActiveModel {
-                id: Default::default(),
-                santa_id: Set(message_dto.santa_id),
-                child_id: Set(message_dto.child_id),
-                message: Set(message_dto.message),
-                create_date: Set(message_dto.create_date),
-            };
-            if let Err(x) = new_message.insert(&self.db).await {
+            Ok::<_, DbErr>(reply_to)
+        }
+        .await;
+        result.unwrap_or_else(|x| {
+            log::error!("Error accessing the database: {:?}", x);
+            None
+        })
+    }
+
+    async fn clear_reply_to<C: ConnectionTrait>(conn: &C, user_id: i64) -> Result<(), DbErr> {
+        user::Entity::update_many()
+            .col_expr(user::Column::ReplyToMessageId, Expr::value(Option::<i32>::None))
+            .filter(user::Column::Id.eq(user_id))
+            .exec(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Обновляет текст (подпись) сообщения переписки после его редактирования
+    pub async fn update_chat_message_text(&self, id: i64, text: Option<String>) {
+        let result = chat_message::Entity::update_many()
+            .col_expr(chat_message::Column::Text, Expr::value(text))
+            .filter(chat_message::Column::Id.eq(id))
+            .exec(&self.db)
+            .await;
+        if let Err(x) = result {
+            log::error!("Error accessing the database: {:?}", x);
+        }
+    }
+
+    pub async fn delete_chat_message(&self, id: i64) -> bool {
+        chat_message::Entity::delete_many()
+            .filter(chat_message::Column::Id.eq(id))
+            .exec(&self.db)
+            .await
+            .map(|result| result.rows_affected > 0)
+            .unwrap_or_else(|x| {
                 log::error!("Error accessing the database: {:?}", x);
-            };
+                false
+            })
+    }
+
+    /// Страница переписки Санты и подопечного в порядке отправки и число страниц.
+    /// Страница 0 — самые новые сообщения.
+    pub async fn get_chat_page(
+        &self,
+        game_id: i64,
+        santa_id: i64,
+        child_id: i64,
+        page: u64,
+        page_size: u64,
+    ) -> (Vec<Message>, u64) {
+        let paginator = chat_message::Entity::find()
+            .filter(chat_message::Column::GameId.eq(game_id))
+            .filter(chat_message::Column::SantaId.eq(santa_id))
+            .filter(chat_message::Column::ChildId.eq(child_id))
+            .filter(chat_message::Column::Archived.eq(false))
+            .order_by_desc(chat_message::Column::Id)
+            .paginate(&self.db, page_size);
+        let pages = paginator.num_pages().await;
+        let messages = paginator.fetch_page(page).await;
+        match (messages, pages) {
+            (Ok(messages), Ok(pages)) => (
+                messages
+                    .into_iter()
+                    .rev()
+                    .filter_map(Message::to_message)
+                    .collect(),
+                pages,
+            ),
+            (Err(x), _) | (_, Err(x)) => {
+                log::error!("Error accessing the database: {:?}", x);
+                (vec![], 0)
+            }
         }
     }
 
     pub async fn save_user(&self, user_dto: User) {
-        if let Some(user) = self.find_user(user_dto.id).await {
+        if let Err(x) = Self::upsert_user(&self.db, user_dto).await {
+            log::error!("Error accessing the database: {:?}", x);
+        }
+    }
+
+    async fn upsert_user<C: ConnectionTrait>(conn: &C, user_dto: User) -> Result<(), DbErr> {
+        if let Some(user) = Self::find_user_in(conn, user_dto.game_id, user_dto.id).await? {
             let mut user: user::ActiveModel = user.into();
             user.username = Set(user_dto.username);
             user.wish_text = Set(user_dto.wish_text);
             user.city = Set(user_dto.city);
+            user.group_id = Set(user_dto.group_id);
             user.child = Set(user_dto.child);
             user.santa = Set(user_dto.santa);
             user.state = Set(match user_dto.state {
                 Some(state) => state.to_string(),
                 _ => "".to_string(),
             });
-            if let Err(x) = user.update(&self.db).await {
-                log::error!("Error accessing the database: {:?}", x);
-            }
+            user.update(conn).await?;
         } else {
             let new_user = user::ActiveModel {
                 id: Set(user_dto.id),
+                game_id: Set(user_dto.game_id),
                 chat_id: Set(user_dto.chat_id),
                 child: Default::default(),
                 santa: Default::default(),
                 nickname: Set(user_dto.nickname),
                 username: Set(user_dto.username),
                 city: Set(user_dto.city),
+                group_id: Set(user_dto.group_id),
+                reply_to_message_id: NotSet,
                 wish_text: Set(user_dto.wish_text),
                 state: Set(user_dto
                     .state
@@ -98,44 +269,628 @@ impl DatabaseHandler {
                     .to_string()),
                 create_date: Set(user_dto.create_date),
             };
-            if let Err(x) = new_user.insert(&self.db).await {
+            new_user.insert(conn).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn save_exclusion(&self, exclusion_dto: Exclusion) {
+        let new_exclusion = exclusion::ActiveModel {
+            id: Default::default(),
+            game_id: Set(exclusion_dto.game_id),
+            santa_id: Set(exclusion_dto.santa_id),
+            child_id: Set(exclusion_dto.child_id),
+            symmetric: Set(exclusion_dto.symmetric),
+            create_date: Set(exclusion_dto.create_date),
+        };
+        if let Err(x) = new_exclusion.insert(&self.db).await {
+            log::error!("Error accessing the database: {:?}", x);
+        };
+    }
+
+    pub async fn delete_exclusion(&self, game_id: i64, exclusion_id: i64) -> bool {
+        exclusion::Entity::delete_many()
+            .filter(exclusion::Column::Id.eq(exclusion_id))
+            .filter(exclusion::Column::GameId.eq(game_id))
+            .exec(&self.db)
+            .await
+            .map(|result| result.rows_affected > 0)
+            .unwrap_or_else(|x| {
                 log::error!("Error accessing the database: {:?}", x);
-            };
+                false
+            })
+    }
+
+    pub async fn get_all_exclusions(&self, game_id: i64) -> Vec<Exclusion> {
+        exclusion::Entity::find()
+            .filter(exclusion::Column::GameId.eq(game_id))
+            .all(&self.db)
+            .await
+            .map(Exclusion::to_exclusions)
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                vec![]
+            })
+    }
+
+    /// Атомарно вносит точечные изменения в распределение сезона (опоздавшие и выбывшие):
+    /// сохраняет пользователей, удаляет из истории разорванные пары, добавляет новые
+    /// и архивирует переписку выбывших `archived_users`
+    pub async fn amend_distribution(
+        &self,
+        game_id: i64,
+        user_dtos: Vec<User>,
+        season: i32,
+        removed: Vec<Assignment>,
+        added: Vec<Assignment>,
+        archived_users: Vec<i64>,
+    ) -> bool {
+        let result: Result<(), DbErr> = async {
+            let txn = self.db.begin().await?;
+            for user in user_dtos.into_iter() {
+                Self::upsert_user(&txn, user).await?;
+            }
+            for assignment in removed.into_iter() {
+                assignment_history::Entity::delete_many()
+                    .filter(assignment_history::Column::GameId.eq(game_id))
+                    .filter(assignment_history::Column::Season.eq(season))
+                    .filter(assignment_history::Column::SantaId.eq(assignment.santa_id))
+                    .filter(assignment_history::Column::ChildId.eq(assignment.child_id))
+                    .exec(&txn)
+                    .await?;
+            }
+            for assignment in added.into_iter() {
+                Self::insert_assignment(&txn, game_id, season, assignment).await?;
+            }
+            for user_id in archived_users.into_iter() {
+                chat_message::Entity::update_many()
+                    .col_expr(chat_message::Column::Archived, Expr::value(true))
+                    .filter(chat_message::Column::GameId.eq(game_id))
+                    .filter(
+                        Condition::any()
+                            .add(chat_message::Column::SantaId.eq(user_id))
+                            .add(chat_message::Column::ChildId.eq(user_id)),
+                    )
+                    .exec(&txn)
+                    .await?;
+            }
+            txn.commit().await
         }
+        .await;
+        result
+            .map_err(|x| log::error!("Error accessing the database: {:?}", x))
+            .is_ok()
+    }
+
+    async fn replace_history<C: ConnectionTrait>(
+        conn: &C,
+        game_id: i64,
+        season: i32,
+        assignments: Vec<Assignment>,
+    ) -> Result<(), DbErr> {
+        assignment_history::Entity::delete_many()
+            .filter(assignment_history::Column::GameId.eq(game_id))
+            .filter(assignment_history::Column::Season.eq(season))
+            .exec(conn)
+            .await?;
+        for assignment in assignments.into_iter() {
+            Self::insert_assignment(conn, game_id, season, assignment).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_assignment<C: ConnectionTrait>(
+        conn: &C,
+        game_id: i64,
+        season: i32,
+        assignment: Assignment,
+    ) -> Result<(), DbErr> {
+        let new_assignment = assignment_history::ActiveModel {
+            id: Default::default(),
+            game_id: Set(game_id),
+            season: Set(season),
+            santa_id: Set(assignment.santa_id),
+            child_id: Set(assignment.child_id),
+            previous_state: Set(assignment.previous_state.map(|state| state.to_string())),
+            create_date: Set(assignment.create_date),
+        };
+        new_assignment.insert(conn).await?;
+        Ok(())
+    }
+
+    /// История распределений за сезоны `[from_season, to_season)`
+    pub async fn get_history(
+        &self,
+        game_id: i64,
+        from_season: i32,
+        to_season: i32,
+    ) -> Vec<Assignment> {
+        assignment_history::Entity::find()
+            .filter(assignment_history::Column::GameId.eq(game_id))
+            .filter(assignment_history::Column::Season.gte(from_season))
+            .filter(assignment_history::Column::Season.lt(to_season))
+            .all(&self.db)
+            .await
+            .map(Assignment::to_assignments)
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                vec![]
+            })
+    }
+
+    pub async fn save_draw(&self, draw_dto: Draw) -> Option<Draw> {
+        Self::upsert_draw(&self.db, draw_dto)
+            .await
+            .map_err(|x| log::error!("Error accessing the database: {:?}", x))
+            .ok()
+    }
+
+    async fn upsert_draw<C: ConnectionTrait>(conn: &C, draw_dto: Draw) -> Result<Draw, DbErr> {
+        let draw = draw::ActiveModel {
+            id: match draw_dto.id {
+                0 => NotSet,
+                id => Unchanged(id),
+            },
+            game_id: Set(draw_dto.game_id),
+            season: Set(draw_dto.season),
+            mode: Set(draw_dto.mode.to_string()),
+            history_seasons: Set(draw_dto.history_seasons as i32),
+            seed: Set(draw_dto.seed),
+            commitment: Set(draw_dto.commitment),
+            assignment_hash: Set(draw_dto.assignment_hash),
+            revealed: Set(draw_dto.revealed),
+            participants: Set(draw_dto
+                .participants
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(",")),
+            input: Set(draw_dto.input.map(|input| input.to_string())),
+            create_date: Set(draw_dto.create_date),
+        };
+        let draw = match draw_dto.id {
+            0 => draw.insert(conn).await?,
+            _ => draw.update(conn).await?,
+        };
+        Ok(Draw::to_draw(draw))
     }
 
-    pub async fn find_message(&self, santa_id: i64, child_id: i64) -> Option<message::Model> {
-        message::Entity::find()
-            .filter(message::Column::SantaId.eq(santa_id))
-            .filter(message::Column::ChildId.eq(child_id))
+    pub async fn find_last_draw(&self, game_id: i64) -> Option<Draw> {
+        draw::Entity::find()
+            .filter(draw::Column::GameId.eq(game_id))
+            .order_by_desc(draw::Column::Id)
             .one(&self.db)
             .await
+            .map(|draw| draw.map(Draw::to_draw))
             .unwrap_or_else(|x| {
                 log::error!("Error accessing the database: {:?}", x);
                 None
             })
     }
-    pub async fn find_user(&self, user_id: i64) -> Option<Model> {
-        user::Entity::find()
-            .filter(user::Column::Id.eq(user_id))
+
+    pub async fn save_game(&self, game_dto: Game) -> Option<Game> {
+        Self::upsert_game(&self.db, game_dto)
+            .await
+            .map_err(|x| log::error!("Error accessing the database: {:?}", x))
+            .ok()
+    }
+
+    /// Создает игру вместе с ее владельцем
+    pub async fn create_game(&self, game_dto: Game, owner_id: i64) -> Option<Game> {
+        let result: Result<Game, DbErr> = async {
+            let txn = self.db.begin().await?;
+            let game = Self::upsert_game(&txn, game_dto).await?;
+            Self::insert_admin(
+                &txn,
+                GameAdmin {
+                    game_id: game.id,
+                    user_id: owner_id,
+                    role: AdminRole::Owner,
+                    create_date: game.create_date,
+                },
+            )
+            .await?;
+            txn.commit().await?;
+            Ok(game)
+        }
+        .await;
+        result
+            .map_err(|x| log::error!("Error accessing the database: {:?}", x))
+            .ok()
+    }
+
+    async fn upsert_game<C: ConnectionTrait>(conn: &C, game_dto: Game) -> Result<Game, DbErr> {
+        let game = game::ActiveModel {
+            id: match game_dto.id {
+                0 => NotSet,
+                id => Unchanged(id),
+            },
+            name: Set(game_dto.name),
+            state: Set(game_dto.state.to_string()),
+            join_code: Set(game_dto.join_code),
+            join_code_expires: Set(game_dto.join_code_expires),
+            budget: Set(game_dto.budget),
+            currency: Set(game_dto.currency),
+            create_date: Set(game_dto.create_date),
+        };
+        let game = match game_dto.id {
+            0 => game.insert(conn).await?,
+            _ => game.update(conn).await?,
+        };
+        Ok(Game::to_game(game))
+    }
+
+    pub async fn save_admin(&self, admin_dto: GameAdmin) -> bool {
+        Self::insert_admin(&self.db, admin_dto)
+            .await
+            .map_err(|x| log::error!("Error accessing the database: {:?}", x))
+            .is_ok()
+    }
+
+    async fn insert_admin<C: ConnectionTrait>(conn: &C, admin_dto: GameAdmin) -> Result<(), DbErr> {
+        let new_admin = game_admin::ActiveModel {
+            id: Default::default(),
+            game_id: Set(admin_dto.game_id),
+            user_id: Set(admin_dto.user_id),
+            role: Set(admin_dto.role.to_string()),
+            create_date: Set(admin_dto.create_date),
+        };
+        new_admin.insert(conn).await?;
+        Ok(())
+    }
+
+    /// Снимает права администратора; владельца игры снять нельзя
+    pub async fn delete_admin(&self, game_id: i64, user_id: i64) -> bool {
+        game_admin::Entity::delete_many()
+            .filter(game_admin::Column::GameId.eq(game_id))
+            .filter(game_admin::Column::UserId.eq(user_id))
+            .filter(game_admin::Column::Role.ne(AdminRole::Owner.to_string()))
+            .exec(&self.db)
+            .await
+            .map(|result| result.rows_affected > 0)
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                false
+            })
+    }
+
+    pub async fn find_admin(&self, game_id: i64, user_id: i64) -> Option<GameAdmin> {
+        game_admin::Entity::find()
+            .filter(game_admin::Column::GameId.eq(game_id))
+            .filter(game_admin::Column::UserId.eq(user_id))
             .one(&self.db)
             .await
+            .map(|admin| admin.map(GameAdmin::to_admin))
             .unwrap_or_else(|x| {
                 log::error!("Error accessing the database: {:?}", x);
                 None
             })
     }
-    pub async fn get_user(&self, user_id: i64) -> Option<User> {
-        self.find_user(user_id)
+
+    pub async fn get_admins(&self, game_id: i64) -> Vec<GameAdmin> {
+        game_admin::Entity::find()
+            .filter(game_admin::Column::GameId.eq(game_id))
+            .order_by_asc(game_admin::Column::Id)
+            .all(&self.db)
+            .await
+            .map(|admins| {
+                admins
+                    .into_iter()
+                    .map(GameAdmin::to_admin)
+                    .collect()
+            })
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                vec![]
+            })
+    }
+
+    pub async fn get_groups(&self, game_id: i64) -> Vec<Group> {
+        game_group::Entity::find()
+            .filter(game_group::Column::GameId.eq(game_id))
+            .order_by_asc(game_group::Column::Id)
+            .all(&self.db)
+            .await
+            .map(|groups| {
+                groups
+                    .into_iter()
+                    .map(Group::to_group)
+                    .collect()
+            })
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                vec![]
+            })
+    }
+
+    pub async fn find_group(&self, game_id: i64, group_id: i64) -> Option<Group> {
+        game_group::Entity::find_by_id(group_id)
+            .filter(game_group::Column::GameId.eq(game_id))
+            .one(&self.db)
+            .await
+            .map(|group| group.map(Group::to_group))
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                None
+            })
+    }
+
+    /// Добавляет группу; группа с таким же названием в игре уже может быть, тогда вернется `None`
+    pub async fn save_group(&self, group_dto: Group) -> Option<Group> {
+        let new_group = game_group::ActiveModel {
+            id: Default::default(),
+            game_id: Set(group_dto.game_id),
+            name: Set(group_dto.name),
+            create_date: Set(group_dto.create_date),
+        };
+        new_group
+            .insert(&self.db)
+            .await
+            .map(Group::to_group)
+            .map_err(|x| log::error!("Error accessing the database: {:?}", x))
+            .ok()
+    }
+
+    /// Удаляет группу; ее участники остаются без группы и попадают в общую группу распределения
+    pub async fn delete_group(&self, game_id: i64, group_id: i64) -> bool {
+        let result: Result<bool, DbErr> = async {
+            let txn = self.db.begin().await?;
+            let deleted = game_group::Entity::delete_many()
+                .filter(game_group::Column::GameId.eq(game_id))
+                .filter(game_group::Column::Id.eq(group_id))
+                .exec(&txn)
+                .await?;
+            user::Entity::update_many()
+                .col_expr(user::Column::GroupId, Expr::value(Option::<i64>::None))
+                .col_expr(user::Column::City, Expr::value(""))
+                .filter(user::Column::GameId.eq(game_id))
+                .filter(user::Column::GroupId.eq(group_id))
+                .exec(&txn)
+                .await?;
+            txn.commit().await?;
+            Ok(deleted.rows_affected > 0)
+        }
+        .await;
+        result.unwrap_or_else(|x| {
+            log::error!("Error accessing the database: {:?}", x);
+            false
+        })
+    }
+
+    /// Планирует действие игры; если оно уже было запланировано, переносит его и снова делает невыполненным
+    pub async fn save_schedule(&self, schedule_dto: Schedule) -> bool {
+        let schedule = schedule::ActiveModel {
+            id: NotSet,
+            game_id: Set(schedule_dto.game_id),
+            action: Set(schedule_dto.action.to_string()),
+            args: Set(schedule_dto.args),
+            run_at: Set(schedule_dto.run_at),
+            done_at: Set(None),
+            attempts: Set(0),
+            create_date: Set(schedule_dto.create_date),
+        };
+        schedule::Entity::insert(schedule)
+            .on_conflict(
+                OnConflict::columns([schedule::Column::GameId, schedule::Column::Action])
+                    .update_columns([
+                        schedule::Column::Args,
+                        schedule::Column::RunAt,
+                        schedule::Column::DoneAt,
+                        schedule::Column::Attempts,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await
+            .map_err(|x| log::error!("Error accessing the database: {:?}", x))
+            .is_ok()
+    }
+
+    pub async fn delete_schedule(&self, game_id: i64, action: ScheduledAction) -> bool {
+        schedule::Entity::delete_many()
+            .filter(schedule::Column::GameId.eq(game_id))
+            .filter(schedule::Column::Action.eq(action.to_string()))
+            .exec(&self.db)
+            .await
+            .map(|result| result.rows_affected > 0)
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                false
+            })
+    }
+
+    pub async fn find_schedule(&self, game_id: i64, action: ScheduledAction) -> Option<Schedule> {
+        schedule::Entity::find()
+            .filter(schedule::Column::GameId.eq(game_id))
+            .filter(schedule::Column::Action.eq(action.to_string()))
+            .one(&self.db)
+            .await
+            .map(|schedule| schedule.and_then(Schedule::to_schedule))
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                None
+            })
+    }
+
+    pub async fn get_schedules(&self, game_id: i64) -> Vec<Schedule> {
+        schedule::Entity::find()
+            .filter(schedule::Column::GameId.eq(game_id))
+            .order_by_asc(schedule::Column::RunAt)
+            .all(&self.db)
+            .await
+            .map(|schedules| {
+                schedules
+                    .into_iter()
+                    .filter_map(Schedule::to_schedule)
+                    .collect()
+            })
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                vec![]
+            })
+    }
+
+    /// Невыполненные действия всех игр, время которых наступило, в порядке времени
+    pub async fn get_due_schedules(&self, now: DateTimeWithTimeZone) -> Vec<Schedule> {
+        schedule::Entity::find()
+            .filter(schedule::Column::DoneAt.is_null())
+            .filter(schedule::Column::RunAt.lte(now))
+            .order_by_asc(schedule::Column::RunAt)
+            .all(&self.db)
+            .await
+            .map(|schedules| {
+                schedules
+                    .into_iter()
+                    .filter_map(Schedule::to_schedule)
+                    .collect()
+            })
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                vec![]
+            })
+    }
+
+    pub async fn mark_schedule_done(&self, schedule_id: i64, done_at: DateTimeWithTimeZone) {
+        let result = schedule::Entity::update_many()
+            .col_expr(schedule::Column::DoneAt, Expr::value(done_at))
+            .filter(schedule::Column::Id.eq(schedule_id))
+            .exec(&self.db)
+            .await;
+        if let Err(x) = result {
+            log::error!("Error accessing the database: {:?}", x);
+        }
+    }
+
+    pub async fn set_schedule_attempts(&self, schedule_id: i64, attempts: i32) {
+        let result = schedule::Entity::update_many()
+            .col_expr(schedule::Column::Attempts, Expr::value(attempts))
+            .filter(schedule::Column::Id.eq(schedule_id))
+            .exec(&self.db)
+            .await;
+        if let Err(x) = result {
+            log::error!("Error accessing the database: {:?}", x);
+        }
+    }
+
+    pub async fn get_game(&self, game_id: i64) -> Option<Game> {
+        game::Entity::find_by_id(game_id)
+            .one(&self.db)
+            .await
+            .map(|game| game.map(Game::to_game))
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                None
+            })
+    }
+
+    pub async fn find_game_by_code(&self, join_code: &str) -> Option<Game> {
+        game::Entity::find()
+            .filter(game::Column::JoinCode.eq(join_code))
+            .one(&self.db)
+            .await
+            .map(|game| game.map(Game::to_game))
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                None
+            })
+    }
+
+    /// Игры, в которых пользователь участвует или которые он организует
+    pub async fn get_games(&self, user_id: i64) -> Vec<Game> {
+        let result: Result<Vec<game::Model>, DbErr> = async {
+            let mut game_ids: Vec<i64> = user::Entity::find()
+                .filter(user::Column::Id.eq(user_id))
+                .all(&self.db)
+                .await?
+                .into_iter()
+                .map(|user| user.game_id)
+                .collect();
+            game_ids.extend(
+                game_admin::Entity::find()
+                    .filter(game_admin::Column::UserId.eq(user_id))
+                    .all(&self.db)
+                    .await?
+                    .into_iter()
+                    .map(|admin| admin.game_id),
+            );
+            game::Entity::find()
+                .filter(game::Column::Id.is_in(game_ids))
+                .order_by_asc(game::Column::Id)
+                .all(&self.db)
+                .await
+        }
+        .await;
+        result
+            .map(|games| games.into_iter().map(Game::to_game).collect())
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                vec![]
+            })
+    }
+
+    /// Текущая игра пользователя, по умолчанию — первая игра бота
+    pub async fn get_active_game(&self, user_id: i64) -> i64 {
+        active_game::Entity::find_by_id(user_id)
+            .one(&self.db)
+            .await
+            .map(|active| active.map(|active| active.game_id))
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                None
+            })
+            .unwrap_or(DEFAULT_GAME_ID)
+    }
+
+    pub async fn set_active_game(&self, user_id: i64, game_id: i64) {
+        let active = active_game::ActiveModel {
+            user_id: Set(user_id),
+            game_id: Set(game_id),
+        };
+        let result = active_game::Entity::insert(active)
+            .on_conflict(
+                OnConflict::column(active_game::Column::UserId)
+                    .update_column(active_game::Column::GameId)
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await;
+        if let Err(x) = result {
+            log::error!("Error accessing the database: {:?}", x);
+        }
+    }
+
+    pub async fn find_user(&self, game_id: i64, user_id: i64) -> Option<Model> {
+        Self::find_user_in(&self.db, game_id, user_id)
+            .await
+            .unwrap_or_else(|x| {
+                log::error!("Error accessing the database: {:?}", x);
+                None
+            })
+    }
+
+    async fn find_user_in<C: ConnectionTrait>(
+        conn: &C,
+        game_id: i64,
+        user_id: i64,
+    ) -> Result<Option<Model>, DbErr> {
+        user::Entity::find()
+            .filter(user::Column::Id.eq(user_id))
+            .filter(user::Column::GameId.eq(game_id))
+            .one(conn)
+            .await
+    }
+    pub async fn get_user(&self, game_id: i64, user_id: i64) -> Option<User> {
+        self.find_user(game_id, user_id)
             .await
             .map(|user| User::to_user(user))
     }
 
-    pub async fn get_all_users(&self) -> Vec<User> {
+    pub async fn get_all_users(&self, game_id: i64) -> Vec<User> {
         user::Entity::find()
-            .from_raw_sql(Statement::from_string(
+            .from_raw_sql(Statement::from_sql_and_values(
                 DbBackend::Postgres,
-                r#"select id, chat_id, nickname, username, wish_text, state, city, child, santa, create_date from public.user"#.to_string()))
+                r#"select id, game_id, chat_id, nickname, username, wish_text, state, city, group_id, reply_to_message_id, child, santa, create_date from public.user where game_id = $1"#,
+                [game_id.into()]))
             .all(&self.db)
             .await
             .map(|user| User::to_users(user))
@@ -145,3 +900,18 @@ impl DatabaseHandler {
             })
     }
 }
+
+/// Сообщения, которые видны в чате Санты (`in_santa_chat`) или подопечного под id `message_id`
+fn chat_side(in_santa_chat: bool, message_id: i32) -> Condition {
+    Condition::any()
+        .add(
+            Condition::all()
+                .add(chat_message::Column::FromSanta.eq(in_santa_chat))
+                .add(chat_message::Column::SenderMessageId.eq(message_id)),
+        )
+        .add(
+            Condition::all()
+                .add(chat_message::Column::FromSanta.eq(!in_santa_chat))
+                .add(chat_message::Column::RecipientMessageId.eq(message_id)),
+        )
+}
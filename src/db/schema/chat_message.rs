@@ -0,0 +1,25 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "chat_message")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub game_id: i64,
+    pub santa_id: i64,
+    pub child_id: i64,
+    pub from_santa: bool,
+    pub kind: String,
+    pub text: Option<String>,
+    pub file_id: Option<String>,
+    pub sender_message_id: Option<i32>,
+    pub recipient_message_id: Option<i32>,
+    pub header_message_id: Option<i32>,
+    pub archived: bool,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "draw")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub game_id: i64,
+    pub season: i32,
+    pub mode: String,
+    pub history_seasons: i32,
+    pub seed: String,
+    pub assignment_hash: Option<String>,
+    pub commitment: String,
+    pub revealed: bool,
+    pub participants: String,
+    pub input: Option<String>,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,13 +1,14 @@
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
-#[sea_orm(table_name = "message")]
+#[sea_orm(table_name = "exclusion")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i64,
+    pub game_id: i64,
     pub santa_id: i64,
     pub child_id: i64,
-    pub message: String,
+    pub symmetric: bool,
     pub create_date: DateTimeWithTimeZone,
 }
 
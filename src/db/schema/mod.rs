@@ -1,2 +1,10 @@
-pub mod message;
+pub mod active_game;
+pub mod assignment_history;
+pub mod chat_message;
+pub mod draw;
+pub mod exclusion;
+pub mod game;
+pub mod game_admin;
+pub mod game_group;
+pub mod schedule;
 pub mod user;
@@ -5,6 +5,8 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub game_id: i64,
     pub chat_id: i64,
     pub child: Option<i64>,
     pub santa: Option<i64>,
@@ -13,6 +15,8 @@ pub struct Model {
     pub wish_text: String,
     pub state: String,
     pub city: String,
+    pub group_id: Option<i64>,
+    pub reply_to_message_id: Option<i32>,
     pub create_date: DateTimeWithTimeZone,
 }
 
@@ -0,0 +1,843 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+use strum_macros::{Display, EnumString};
+
+use crate::types::{Exclusion, Group, User};
+
+/// Режим распределения, передается аргументом команды `/distribute`
+#[derive(Clone, Copy, Debug, Default, Display, EnumString, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum DistributionMode {
+    /// Санта и подопечный всегда из разных групп (городов, офисов, филиалов)
+    #[default]
+    #[strum(to_string = "groups", serialize = "cities")]
+    Groups,
+    /// Общий пул участников без учета городов
+    Pool,
+    /// Один большой круг A -> B -> C -> ... -> A без взаимных пар и маленьких циклов.
+    /// Если участники из нескольких групп, Санта и подопечный тоже из разных групп.
+    Cycle,
+}
+
+/// Участник распределения: идентификатор и группа, к которой он относится
+#[derive(Clone, Debug, PartialEq)]
+pub struct Participant {
+    pub id: i64,
+    pub group: String,
+}
+
+impl Participant {
+    /// Группа участника берется из списка групп игры; участники без группы попадают в общую группу ""
+    pub fn new(user: &User, groups: &[Group]) -> Self {
+        Participant {
+            id: user.id,
+            group: groups
+                .iter()
+                .find(|group| Some(group.id) == user.group_id)
+                .map(|group| group.name.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Пара Санта -> подопечный
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pair {
+    pub santa: i64,
+    pub child: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DistributionError {
+    NotEnoughParticipants(usize),
+    GroupTooLarge {
+        group: String,
+        size: usize,
+        total: usize,
+    },
+    Unsatisfiable,
+}
+
+impl Display for DistributionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DistributionError::NotEnoughParticipants(count) => write!(
+                f,
+                "Для распределения нужно хотя бы 2 участника, сейчас: {}",
+                count
+            ),
+            DistributionError::GroupTooLarge { group, size, total } => write!(
+                f,
+                "Распределение невозможно: в группе «{}» {} из {} участников. \
+                 Санта и подопечный должны быть из разных групп, поэтому в одной группе \
+                 может быть не больше половины участников",
+                group, size, total
+            ),
+            DistributionError::Unsatisfiable => write!(
+                f,
+                "Распределение невозможно: с учетом групп и запрещенных пар \
+                 не получается подобрать подопечного каждому участнику"
+            ),
+        }
+    }
+}
+
+/// Ограничения распределения: пары, которые не должны совпасть
+#[derive(Clone, Debug, Default)]
+pub struct Constraints {
+    forbidden: HashSet<(i64, i64)>,
+}
+
+impl Constraints {
+    pub fn from_exclusions(exclusions: &[Exclusion]) -> Self {
+        let mut constraints = Constraints::default();
+        for exclusion in exclusions {
+            constraints.forbid(exclusion.santa_id, exclusion.child_id);
+            if exclusion.symmetric {
+                constraints.forbid(exclusion.child_id, exclusion.santa_id);
+            }
+        }
+        constraints
+    }
+
+    pub fn forbid(&mut self, santa: i64, child: i64) {
+        self.forbidden.insert((santa, child));
+    }
+
+    pub fn is_allowed(&self, santa: i64, child: i64) -> bool {
+        !self.forbidden.contains(&(santa, child))
+    }
+
+    /// Запрещенные пары по возрастанию id Санты и подопечного
+    pub fn pairs(&self) -> Vec<Pair> {
+        let mut pairs: Vec<Pair> = self
+            .forbidden
+            .iter()
+            .map(|&(santa, child)| Pair { santa, child })
+            .collect();
+        pairs.sort_by_key(|pair| (pair.santa, pair.child));
+        pairs
+    }
+}
+
+/// Входные данные распределения на момент его проведения: группы участников (id участника
+/// и id его группы), запрещенные пары и пары прошлых сезонов, от последнего к более старым.
+/// По ним распределение воспроизводится, даже если запреты или группы игры потом изменились.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DrawInput {
+    pub groups: Vec<(i64, i64)>,
+    pub forbidden: Vec<Pair>,
+    pub history: Vec<Vec<Pair>>,
+}
+
+impl DrawInput {
+    /// Участники распределения; группа участника — id его группы, без группы — ""
+    pub fn participants(&self, ids: &[i64]) -> Vec<Participant> {
+        ids.iter()
+            .map(|&id| Participant {
+                id,
+                group: self
+                    .groups
+                    .iter()
+                    .find(|(participant, _)| *participant == id)
+                    .map(|(_, group)| group.to_string())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    pub fn constraints(&self) -> Constraints {
+        let mut constraints = Constraints::default();
+        for pair in self.forbidden.iter() {
+            constraints.forbid(pair.santa, pair.child);
+        }
+        constraints
+    }
+}
+
+/// Формат хранения в БД: строка групп `1:5,2:7`, строка запретов `1>2,2>1`
+/// и по строке пар на каждый прошлый сезон
+impl Display for DrawInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let groups = self
+            .groups
+            .iter()
+            .map(|(participant, group)| format!("{}:{}", participant, group))
+            .collect::<Vec<String>>()
+            .join(",");
+        write!(f, "{}\n{}", groups, format_pairs(&self.forbidden))?;
+        for season in self.history.iter() {
+            write!(f, "\n{}", format_pairs(season))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DrawInput {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.split('\n');
+        let groups = split_list(lines.next().ok_or(())?)
+            .map(|group| {
+                let (participant, group) = group.split_once(':').ok_or(())?;
+                Ok((
+                    participant.parse().map_err(|_| ())?,
+                    group.parse().map_err(|_| ())?,
+                ))
+            })
+            .collect::<Result<Vec<(i64, i64)>, ()>>()?;
+        let forbidden = parse_pairs(lines.next().ok_or(())?)?;
+        let history = lines.map(parse_pairs).collect::<Result<Vec<Vec<Pair>>, ()>>()?;
+        Ok(DrawInput {
+            groups,
+            forbidden,
+            history,
+        })
+    }
+}
+
+fn format_pairs(pairs: &[Pair]) -> String {
+    pairs
+        .iter()
+        .map(|pair| format!("{}>{}", pair.santa, pair.child))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn parse_pairs(line: &str) -> Result<Vec<Pair>, ()> {
+    split_list(line)
+        .map(|pair| {
+            let (santa, child) = pair.split_once('>').ok_or(())?;
+            Ok(Pair {
+                santa: santa.parse().map_err(|_| ())?,
+                child: child.parse().map_err(|_| ())?,
+            })
+        })
+        .collect()
+}
+
+fn split_list(line: &str) -> impl Iterator<Item = &str> {
+    line.split(',').filter(|item| !item.is_empty())
+}
+
+/// Сколько попыток и шагов перебора в каждой допускается при поиске единого цикла дарения
+const CYCLE_SEARCH_ATTEMPTS: usize = 20;
+const CYCLE_SEARCH_LIMIT: usize = 50_000;
+
+/// Распределение участников: никто не дарит подарок сам себе и запрещенным парам,
+/// а в режиме групп Санта и подопечный всегда из разных групп.
+pub fn distribute<R: Rng + ?Sized>(
+    participants: &[Participant],
+    mode: DistributionMode,
+    constraints: &Constraints,
+    rng: &mut R,
+) -> Result<Vec<Pair>, DistributionError> {
+    let total = participants.len();
+    if total < 2 {
+        return Err(DistributionError::NotEnoughParticipants(total));
+    }
+    let separate_groups = separates_groups(mode, participants);
+    if separate_groups {
+        check_group_sizes(participants)?;
+    }
+
+    let allowed = |santa: &Participant, child: &Participant| {
+        santa.id != child.id
+            && (!separate_groups || santa.group != child.group)
+            && constraints.is_allowed(santa.id, child.id)
+    };
+    match mode {
+        DistributionMode::Cycle => find_cycle(participants, allowed, rng),
+        _ => find_matching(participants, allowed, rng),
+    }
+    .ok_or(DistributionError::Unsatisfiable)
+}
+
+/// Должны ли Санта и подопечный быть из разных групп
+fn separates_groups(mode: DistributionMode, participants: &[Participant]) -> bool {
+    match mode {
+        DistributionMode::Groups => true,
+        DistributionMode::Pool => false,
+        DistributionMode::Cycle => group_sizes(participants).len() > 1,
+    }
+}
+
+/// Вставка опоздавшего участника в готовое распределение: случайная пара Санта -> подопечный,
+/// для которой новичок подходит и как подопечный Санты, и как Санта подопечного, разрывается,
+/// и новичок встает между ними. Остальные пары не меняются, а единый цикл остается единым.
+/// Возвращает разорванную пару.
+pub fn insert_participant<R: Rng + ?Sized>(
+    pairs: &[Pair],
+    participants: &[Participant],
+    newcomer: &Participant,
+    mode: DistributionMode,
+    constraints: &Constraints,
+    rng: &mut R,
+) -> Option<Pair> {
+    let groups: HashMap<i64, &str> = participants
+        .iter()
+        .chain([newcomer])
+        .map(|participant| (participant.id, participant.group.as_str()))
+        .collect();
+    let separate_groups = separates_groups(mode, participants);
+    let allowed = |santa: i64, child: i64| {
+        santa != child
+            && (!separate_groups || groups.get(&santa) != groups.get(&child))
+            && constraints.is_allowed(santa, child)
+    };
+    let candidates: Vec<&Pair> = pairs
+        .iter()
+        .filter(|pair| allowed(pair.santa, newcomer.id) && allowed(newcomer.id, pair.child))
+        .collect();
+    candidates.choose(rng).map(|&&pair| pair)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VerificationError {
+    InvalidSeed,
+    CommitmentMismatch,
+    Distribution(DistributionError),
+}
+
+impl Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerificationError::InvalidSeed => write!(f, "Seed должен быть 32 байтами в hex"),
+            VerificationError::CommitmentMismatch => {
+                write!(f, "Seed не соответствует опубликованному обязательству")
+            }
+            VerificationError::Distribution(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Seed генератора случайных чисел, от которого полностью зависит распределение
+pub type Seed = [u8; 32];
+
+/// Криптографически случайный seed, который нельзя предсказать заранее
+pub fn random_seed() -> Seed {
+    let mut seed = Seed::default();
+    OsRng.fill_bytes(&mut seed);
+    seed
+}
+
+/// Обязательство (commitment) на seed: публикуется до распределения, чтобы после раскрытия
+/// seed любой мог убедиться, что распределение проводилось именно с ним
+pub fn commitment(seed: &Seed) -> String {
+    hex::encode(Sha256::digest(seed))
+}
+
+/// Проверка раскрытого распределения: seed должен соответствовать опубликованному
+/// обязательству, а результат совпадает с тем, что получит любой участник,
+/// повторив распределение с тем же seed и списком участников
+pub fn verify(
+    seed_hex: &str,
+    expected_commitment: &str,
+    participants: &[Participant],
+    mode: DistributionMode,
+    constraints: &Constraints,
+    history: &[Vec<Pair>],
+) -> Result<Vec<Pair>, VerificationError> {
+    let seed: Seed = hex::decode(seed_hex.trim())
+        .ok()
+        .and_then(|seed| seed.try_into().ok())
+        .ok_or(VerificationError::InvalidSeed)?;
+    if commitment(&seed) != expected_commitment {
+        return Err(VerificationError::CommitmentMismatch);
+    }
+    seeded_draw(&seed, participants, mode, constraints, history)
+        .map(|(pairs, _)| pairs)
+        .map_err(VerificationError::Distribution)
+}
+
+/// Воспроизводимое распределение: один и тот же seed при тех же участниках, запретах
+/// и истории всегда дает одни и те же пары, независимо от порядка строк в БД
+pub fn seeded_draw(
+    seed: &Seed,
+    participants: &[Participant],
+    mode: DistributionMode,
+    constraints: &Constraints,
+    history: &[Vec<Pair>],
+) -> Result<(Vec<Pair>, usize), DistributionError> {
+    let mut participants = participants.to_vec();
+    participants.sort_by_key(|participant| participant.id);
+    let mut rng = ChaCha20Rng::from_seed(*seed);
+    distribute_avoiding_history(&participants, mode, constraints, history, &mut rng)
+}
+
+/// SHA-256 от распределения, упорядоченного по Сантам: позволяет сверить результат,
+/// не раскрывая сами пары
+pub fn assignment_hash(pairs: &[Pair]) -> String {
+    let mut pairs = pairs.to_vec();
+    pairs.sort_by_key(|pair| pair.santa);
+    let mut hasher = Sha256::new();
+    for pair in pairs {
+        hasher.update(format!("{}->{}\n", pair.santa, pair.child));
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Распределение, которое избегает пар из прошлых сезонов (`history`, от последнего к более старым).
+/// Если с учетом всей истории распределить участников невозможно, самые старые сезоны
+/// перестают учитываться по одному, пока распределение не найдется. Запрещенные пары
+/// не ослабляются никогда. Вместе с парами возвращается кол-во учтенных сезонов.
+pub fn distribute_avoiding_history<R: Rng + ?Sized>(
+    participants: &[Participant],
+    mode: DistributionMode,
+    constraints: &Constraints,
+    history: &[Vec<Pair>],
+    rng: &mut R,
+) -> Result<(Vec<Pair>, usize), DistributionError> {
+    let mut seasons = history.len();
+    loop {
+        let mut season_constraints = constraints.clone();
+        for pair in history[..seasons].iter().flatten() {
+            season_constraints.forbid(pair.santa, pair.child);
+        }
+        match distribute(participants, mode, &season_constraints, rng) {
+            Ok(pairs) => return Ok((pairs, seasons)),
+            Err(DistributionError::Unsatisfiable) if seasons > 0 => seasons -= 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Кол-во участников в каждой группе
+pub fn group_sizes(participants: &[Participant]) -> BTreeMap<&str, usize> {
+    let mut groups: BTreeMap<&str, usize> = BTreeMap::new();
+    for participant in participants {
+        *groups.entry(participant.group.as_str()).or_default() += 1;
+    }
+    groups
+}
+
+/// Длины циклов дарения (A -> B -> ... -> A) по убыванию
+pub fn cycle_lengths(pairs: &[Pair]) -> Vec<usize> {
+    let children: HashMap<i64, i64> = pairs.iter().map(|pair| (pair.santa, pair.child)).collect();
+    let mut visited: HashSet<i64> = HashSet::new();
+    let mut lengths = vec![];
+    for pair in pairs {
+        let mut length = 0;
+        let mut current = pair.santa;
+        while visited.insert(current) {
+            length += 1;
+            match children.get(&current) {
+                Some(&child) => current = child,
+                None => break,
+            }
+        }
+        if length > 0 {
+            lengths.push(length);
+        }
+    }
+    lengths.sort_by(|a, b| b.cmp(a));
+    lengths
+}
+
+/// Если одна группа больше половины всех участников, кому-то из нее
+/// неизбежно достанется подопечный из своей же группы
+fn check_group_sizes(participants: &[Participant]) -> Result<(), DistributionError> {
+    let total = participants.len();
+    match group_sizes(participants)
+        .into_iter()
+        .max_by_key(|(_, size)| *size)
+    {
+        Some((group, size)) if size * 2 > total => Err(DistributionError::GroupTooLarge {
+            group: group.to_string(),
+            size,
+            total,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Поиск совершенного паросочетания Санта -> подопечный алгоритмом Куна.
+/// Порядок Сант и их кандидатов перемешивается, поэтому каждый запуск дает случайный результат,
+/// а если допустимое распределение существует, оно обязательно будет найдено.
+fn find_matching<R, F>(participants: &[Participant], allowed: F, rng: &mut R) -> Option<Vec<Pair>>
+where
+    R: Rng + ?Sized,
+    F: Fn(&Participant, &Participant) -> bool,
+{
+    let total = participants.len();
+    let candidates: Vec<Vec<usize>> = participants
+        .iter()
+        .map(|santa| {
+            let mut children: Vec<usize> = (0..total)
+                .filter(|&child| allowed(santa, &participants[child]))
+                .collect();
+            children.shuffle(rng);
+            children
+        })
+        .collect();
+    let mut santas: Vec<usize> = (0..total).collect();
+    santas.shuffle(rng);
+
+    let mut santa_of: Vec<Option<usize>> = vec![None; total];
+    for santa in santas {
+        let mut visited = vec![false; total];
+        if !try_assign(santa, &candidates, &mut santa_of, &mut visited) {
+            return None;
+        }
+    }
+    Some(
+        santa_of
+            .into_iter()
+            .enumerate()
+            .map(|(child, santa)| Pair {
+                santa: participants[santa.unwrap()].id,
+                child: participants[child].id,
+            })
+            .collect(),
+    )
+}
+
+/// Поиск единого цикла дарения (гамильтонова цикла) перебором с возвратом.
+/// Следующим выбирается участник с наименьшим числом свободных продолжений (правило Варнсдорфа),
+/// а ветки, из которых уже нельзя вернуться к первому участнику, отсекаются. Перебор делается
+/// несколькими попытками со случайным стартом, каждая ограничена `CYCLE_SEARCH_LIMIT` шагами,
+/// поэтому результат всегда одинаков для одного seed.
+fn find_cycle<R, F>(participants: &[Participant], allowed: F, rng: &mut R) -> Option<Vec<Pair>>
+where
+    R: Rng + ?Sized,
+    F: Fn(&Participant, &Participant) -> bool,
+{
+    let total = participants.len();
+    let candidates: Vec<Vec<usize>> = participants
+        .iter()
+        .map(|santa| {
+            (0..total)
+                .filter(|&child| allowed(santa, &participants[child]))
+                .collect()
+        })
+        .collect();
+
+    for _ in 0..CYCLE_SEARCH_ATTEMPTS {
+        let mut shuffled = candidates.clone();
+        for children in shuffled.iter_mut() {
+            children.shuffle(rng);
+        }
+        let start = rng.gen_range(0..total);
+        let mut search = CycleSearch {
+            candidates: &shuffled,
+            path: vec![start],
+            visited: vec![false; total],
+            steps: 0,
+        };
+        search.visited[start] = true;
+        if search.extend() {
+            let path = search.path;
+            return Some(
+                (0..total)
+                    .map(|i| Pair {
+                        santa: participants[path[i]].id,
+                        child: participants[path[(i + 1) % total]].id,
+                    })
+                    .collect(),
+            );
+        }
+    }
+    None
+}
+
+struct CycleSearch<'a> {
+    candidates: &'a [Vec<usize>],
+    path: Vec<usize>,
+    visited: Vec<bool>,
+    steps: usize,
+}
+
+impl CycleSearch<'_> {
+    /// Продлевает путь по цепочке Санта -> подопечный, пока он не замкнется в цикл из всех участников
+    fn extend(&mut self) -> bool {
+        let last = *self.path.last().unwrap();
+        let start = self.path[0];
+        if self.path.len() == self.candidates.len() {
+            return self.candidates[last].contains(&start);
+        }
+        if !self.can_return_to(start) {
+            return false;
+        }
+
+        let mut next: Vec<(usize, usize)> = self.candidates[last]
+            .iter()
+            .filter(|&&child| !self.visited[child])
+            .map(|&child| (self.free_children(child), child))
+            .collect();
+        next.sort_by_key(|(free, _)| *free);
+        for (_, child) in next {
+            self.steps += 1;
+            if self.steps > CYCLE_SEARCH_LIMIT {
+                return false;
+            }
+            self.visited[child] = true;
+            self.path.push(child);
+            if self.extend() {
+                return true;
+            }
+            self.path.pop();
+            self.visited[child] = false;
+        }
+        false
+    }
+
+    fn free_children(&self, santa: usize) -> usize {
+        self.candidates[santa]
+            .iter()
+            .filter(|&&child| !self.visited[child])
+            .count()
+    }
+
+    /// Остался ли еще не включенный в путь участник, который может дарить первому
+    fn can_return_to(&self, start: usize) -> bool {
+        (0..self.candidates.len())
+            .any(|santa| !self.visited[santa] && self.candidates[santa].contains(&start))
+    }
+}
+
+/// Ищет Санте подопечного, при необходимости переназначая уже занятых (увеличивающая цепь)
+fn try_assign(
+    santa: usize,
+    candidates: &[Vec<usize>],
+    santa_of: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for &child in candidates[santa].iter() {
+        if visited[child] {
+            continue;
+        }
+        visited[child] = true;
+        let free = match santa_of[child] {
+            Some(other) => try_assign(other, candidates, santa_of, visited),
+            None => true,
+        };
+        if free {
+            santa_of[child] = Some(santa);
+            return true;
+        }
+    }
+    false
+}
+
+/// Проставляет участникам `santa`/`child` согласно распределению
+pub fn apply_pairs(users: &mut [User], pairs: &[Pair]) {
+    for pair in pairs {
+        for user in users.iter_mut() {
+            if user.id == pair.santa {
+                user.set_child(pair.child);
+            }
+            if user.id == pair.child {
+                user.set_santa(pair.santa);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Участники с несовпадающими по порядку id, распределенные по `groups` группам
+    fn participants(max: usize, groups: usize) -> impl Strategy<Value = Vec<Participant>> {
+        prop::collection::vec(0..groups, 2..=max).prop_map(|groups| {
+            groups
+                .into_iter()
+                .enumerate()
+                .map(|(i, group)| Participant {
+                    id: 1000 - 7 * i as i64,
+                    group: format!("group-{}", group),
+                })
+                .collect()
+        })
+    }
+
+    fn modes() -> impl Strategy<Value = DistributionMode> {
+        prop_oneof![
+            Just(DistributionMode::Groups),
+            Just(DistributionMode::Pool),
+            Just(DistributionMode::Cycle),
+        ]
+    }
+
+    fn largest_group(participants: &[Participant]) -> usize {
+        group_sizes(participants).into_values().max().unwrap_or(0)
+    }
+
+    /// Каждый участник ровно один раз Санта и ровно один раз подопечный, и никто не дарит сам себе
+    fn assert_complete(participants: &[Participant], pairs: &[Pair]) -> Result<(), TestCaseError> {
+        let ids: HashSet<i64> = participants
+            .iter()
+            .map(|participant| participant.id)
+            .collect();
+        let santas: HashSet<i64> = pairs.iter().map(|pair| pair.santa).collect();
+        let children: HashSet<i64> = pairs.iter().map(|pair| pair.child).collect();
+        prop_assert_eq!(pairs.len(), participants.len());
+        prop_assert_eq!(&santas, &ids);
+        prop_assert_eq!(&children, &ids);
+        prop_assert!(pairs.iter().all(|pair| pair.santa != pair.child));
+        Ok(())
+    }
+
+    fn assert_cross_group(
+        participants: &[Participant],
+        pairs: &[Pair],
+    ) -> Result<(), TestCaseError> {
+        let groups: HashMap<i64, &str> = participants
+            .iter()
+            .map(|participant| (participant.id, participant.group.as_str()))
+            .collect();
+        for pair in pairs {
+            prop_assert_ne!(groups[&pair.santa], groups[&pair.child]);
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn pool_assigns_everyone(participants in participants(40, 3), seed in any::<Seed>()) {
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            let pairs = distribute(&participants, DistributionMode::Pool, &Constraints::default(), &mut rng).unwrap();
+            assert_complete(&participants, &pairs)?;
+        }
+
+        #[test]
+        fn groups_never_match_within_group(participants in participants(40, 4), seed in any::<Seed>()) {
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            match distribute(&participants, DistributionMode::Groups, &Constraints::default(), &mut rng) {
+                Ok(pairs) => {
+                    assert_complete(&participants, &pairs)?;
+                    assert_cross_group(&participants, &pairs)?;
+                }
+                Err(err) => {
+                    prop_assert!(largest_group(&participants) * 2 > participants.len());
+                    let is_group_too_large = matches!(err, DistributionError::GroupTooLarge { .. });
+                    prop_assert!(is_group_too_large);
+                }
+            }
+        }
+
+        #[test]
+        fn cycle_is_single(participants in participants(30, 3), seed in any::<Seed>()) {
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            let several_groups = group_sizes(&participants).len() > 1;
+            match distribute(&participants, DistributionMode::Cycle, &Constraints::default(), &mut rng) {
+                Ok(pairs) => {
+                    assert_complete(&participants, &pairs)?;
+                    prop_assert_eq!(cycle_lengths(&pairs), vec![participants.len()]);
+                    if several_groups {
+                        assert_cross_group(&participants, &pairs)?;
+                    }
+                }
+                Err(_) => {
+                    prop_assert!(several_groups);
+                    prop_assert!(largest_group(&participants) * 2 > participants.len());
+                }
+            }
+        }
+
+        #[test]
+        fn exclusions_are_respected(
+            participants in participants(25, 2),
+            excluded in prop::collection::vec((0..25usize, 0..25usize, any::<bool>()), 0..30),
+            mode in modes(),
+            seed in any::<Seed>(),
+        ) {
+            let exclusions: Vec<Exclusion> = excluded
+                .into_iter()
+                .map(|(santa, child, symmetric)| Exclusion {
+                    santa_id: participants[santa % participants.len()].id,
+                    child_id: participants[child % participants.len()].id,
+                    symmetric,
+                    ..Default::default()
+                })
+                .collect();
+            let constraints = Constraints::from_exclusions(&exclusions);
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            if let Ok(pairs) = distribute(&participants, mode, &constraints, &mut rng) {
+                assert_complete(&participants, &pairs)?;
+                for exclusion in exclusions.iter() {
+                    prop_assert!(!constraints.is_allowed(exclusion.santa_id, exclusion.child_id));
+                    let excluded = Pair { santa: exclusion.santa_id, child: exclusion.child_id };
+                    prop_assert!(!pairs.contains(&excluded));
+                    let reverse = Pair { santa: exclusion.child_id, child: exclusion.santa_id };
+                    prop_assert!(!exclusion.symmetric || !pairs.contains(&reverse));
+                }
+            }
+        }
+
+        #[test]
+        fn seeded_draw_is_deterministic(
+            participants in participants(30, 3),
+            mode in modes(),
+            seed in any::<Seed>(),
+            order_seed in any::<Seed>(),
+        ) {
+            let mut shuffled = participants.clone();
+            shuffled.shuffle(&mut ChaCha20Rng::from_seed(order_seed));
+            let first = seeded_draw(&seed, &participants, mode, &Constraints::default(), &[]);
+            let second = seeded_draw(&seed, &shuffled, mode, &Constraints::default(), &[]);
+            prop_assert_eq!(&first, &second);
+            if let Ok((pairs, _)) = first {
+                let verified = verify(&hex::encode(seed), &commitment(&seed), &participants, mode, &Constraints::default(), &[]);
+                prop_assert_eq!(assignment_hash(&verified.unwrap()), assignment_hash(&pairs));
+            }
+        }
+
+        #[test]
+        fn previous_season_is_avoided(participants in participants(30, 1), seed in any::<Seed>()) {
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            let previous = distribute(&participants, DistributionMode::Pool, &Constraints::default(), &mut rng).unwrap();
+            let (pairs, honoured) = distribute_avoiding_history(
+                &participants,
+                DistributionMode::Pool,
+                &Constraints::default(),
+                std::slice::from_ref(&previous),
+                &mut rng,
+            )
+            .unwrap();
+            assert_complete(&participants, &pairs)?;
+            if honoured == 1 {
+                prop_assert!(pairs.iter().all(|pair| !previous.contains(pair)));
+            }
+        }
+
+        #[test]
+        fn late_participant_keeps_single_cycle(participants in participants(30, 1), seed in any::<Seed>()) {
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            let mut pairs = distribute(&participants, DistributionMode::Cycle, &Constraints::default(), &mut rng).unwrap();
+            let newcomer = Participant { id: 1, group: participants[0].group.clone() };
+            let edge = insert_participant(&pairs, &participants, &newcomer, DistributionMode::Cycle, &Constraints::default(), &mut rng).unwrap();
+            pairs.retain(|pair| *pair != edge);
+            pairs.push(Pair { santa: edge.santa, child: newcomer.id });
+            pairs.push(Pair { santa: newcomer.id, child: edge.child });
+            let everyone: Vec<Participant> = participants.iter().cloned().chain([newcomer]).collect();
+            assert_complete(&everyone, &pairs)?;
+            prop_assert_eq!(cycle_lengths(&pairs), vec![everyone.len()]);
+        }
+    }
+
+    #[test]
+    fn draw_input_round_trips() {
+        let pair = |santa, child| Pair { santa, child };
+        let input = DrawInput {
+            groups: vec![(1, 5), (2, 7)],
+            forbidden: vec![pair(1, 2), pair(2, 1)],
+            history: vec![vec![pair(1, 3), pair(3, 1)], vec![]],
+        };
+        assert_eq!(input.to_string(), "1:5,2:7\n1>2,2>1\n1>3,3>1\n");
+        assert_eq!(DrawInput::from_str(&input.to_string()), Ok(input));
+        assert_eq!(DrawInput::from_str("\n"), Ok(DrawInput::default()));
+        assert_eq!(DrawInput::from_str("1:x\n"), Err(()));
+    }
+}
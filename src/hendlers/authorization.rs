@@ -0,0 +1,56 @@
+use crate::bot::Command;
+use crate::db::DatabaseHandler;
+use crate::types::{AdminRole, Game};
+
+/// Права пользователя в игре, по возрастанию
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Participant,
+    Admin,
+    Owner,
+}
+
+/// Права, которые нужны для команды. Новые команды по умолчанию доступны только организаторам.
+pub fn required_access(command: &Command) -> Access {
+    match command {
+        Command::Start(_)
+        | Command::Help
+        | Command::NewGame(_)
+        | Command::Game(_)
+        | Command::Games
+        | Command::Leave
+        | Command::Delete
+        | Command::VerifyDraw => Access::Participant,
+        Command::GrantAdmin(_) | Command::RevokeAdmin(_) => Access::Owner,
+        _ => Access::Admin,
+    }
+}
+
+pub async fn access(db: &DatabaseHandler, game: &Game, user_id: i64) -> Access {
+    match db
+        .find_admin(game.id, user_id)
+        .await
+        .map(|admin| admin.role)
+    {
+        Some(AdminRole::Owner) => Access::Owner,
+        Some(AdminRole::Admin) => Access::Admin,
+        None => Access::Participant,
+    }
+}
+
+/// Единая проверка прав на команду; все отказы записываются в лог
+pub fn authorize(game: &Game, user_id: i64, access: Access, command: &Command) -> bool {
+    let required = required_access(command);
+    if access < required {
+        log::warn!(
+            "Access denied: user {} with {:?} access tried {:?} in game {}, {:?} required",
+            user_id,
+            access,
+            command,
+            game.id,
+            required
+        );
+        return false;
+    }
+    true
+}
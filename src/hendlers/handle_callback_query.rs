@@ -1,83 +1,183 @@
-use reqwest::Url;
 use teloxide::prelude::*;
-use teloxide::types::{InputFile, KeyboardButton, KeyboardMarkup};
+use teloxide::types::KeyboardRemove;
+use teloxide::utils::html;
 
 use crate::bot::*;
 use crate::db::DatabaseHandler;
-use crate::SantaBot;
+use crate::types::{Group, User};
+use crate::{relay, SantaBot};
 
 pub async fn handle_callback_query(
     db: DatabaseHandler,
     bot: SantaBot,
     query: CallbackQuery,
 ) -> ResponseResult<()> {
-    match query.data.as_deref() {
-        Some(CITY_CALLBACK_MSK) => {
-            select_city(db, &bot, query, MOSCOW_CITY).await?;
+    let user_id = query.from.id.0 as i64;
+    let data = query.data.clone().unwrap_or_default();
+    let message = match query.message.clone() {
+        Some(message) => message,
+        None => {
+            log::warn!("Callback query without a message: {:?}", &query);
+            return Ok(());
         }
-        Some(CITY_CALLBACK_IZH) => {
-            select_city(db, &bot, query, IZHEVSK_CITY).await?;
+    };
+    bot.answer_callback_query(query.id.clone()).await?;
+
+    if let Some((game_id, args)) = parse_callback_data(&data, GROUP_CALLBACK_PREFIX) {
+        let group = match args.parse::<i64>() {
+            Ok(group_id) => db.find_group(game_id, group_id).await,
+            Err(_) => None,
+        };
+        match group {
+            Some(_) if registration_closed(&db, game_id).await => {
+                bot.send_message(
+                    message.chat.id,
+                    "Прости, внучок, регистрация уже закрыта и дедушка больше не принимает заявки",
+                )
+                .await?;
+            }
+            Some(group) => select_group(&db, &bot, &message, game_id, user_id, group).await?,
+            None => {
+                bot.send_message(message.chat.id, "Выберите город из меню.")
+                    .await?;
+            }
         }
-        Some(CHANGE_WISH_CALLBACK) => {
-            let user = db.get_user(query.from.id.0 as i64).await.unwrap();
-            let message = query.message.unwrap();
-            if user.santa.is_some() {
-                bot.edit_message_text(message.chat.id, message.id, "Поздно пить Боржоми! После распределения ролей нельзя менять список пожеланий, можешь написать в чате своему санте").await?;
-            } else {
+    } else if let Some((game_id, args)) = parse_callback_data(&data, HISTORY_CALLBACK_PREFIX) {
+        let page = match (
+            callback_user(&db, &bot, &message, game_id, user_id).await?,
+            relay::parse_history_callback(args),
+        ) {
+            (Some(user), Some((with_child, page))) => {
+                relay::history_page(&db, &user, with_child, page).await
+            }
+            _ => None,
+        };
+        match page {
+            Some(page) => {
+                bot.edit_message_text(message.chat.id, message.id, page.text)
+                    .reply_markup(page.keyboard)
+                    .await?;
+            }
+            None => {
+                bot.edit_message_text(message.chat.id, message.id, "Эта переписка больше недоступна")
+                    .await?;
+            }
+        }
+    } else if let Some((game_id, _)) = parse_callback_data(&data, REPLY_CALLBACK) {
+        relay::start_quick_reply(&bot, &db, game_id, &message).await?;
+    } else if let Some((game_id, args)) = parse_callback_data(&data, SEND_CALLBACK_PREFIX) {
+        if let Some(user) = callback_user(&db, &bot, &message, game_id, user_id).await? {
+            relay::send_to(&bot, &db, &user, args, &message).await?;
+        }
+    } else if let Some((game_id, _)) = parse_callback_data(&data, CHANGE_WISH_CALLBACK) {
+        let user = match callback_user(&db, &bot, &message, game_id, user_id).await? {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+        if user.santa.is_some() {
+            bot.edit_message_text(message.chat.id, message.id, "Поздно пить Боржоми! После распределения ролей нельзя менять список пожеланий, можешь написать в чате своему санте").await?;
+        } else {
+            bot.edit_message_text(
+                message.chat.id,
+                message.id,
+                "Введите список желаний, я его передам Санте",
+            )
+            .await?;
+        }
+    } else if let Some((game_id, _)) = parse_callback_data(&data, LEAVE_CALLBACK) {
+        let user = match callback_user(&db, &bot, &message, game_id, user_id).await? {
+            Some(user) if user.is_registered() => user,
+            _ => return Ok(()),
+        };
+        let game = db.get_game(game_id).await.unwrap_or_default();
+        match MyBot::new()
+            .await
+            .remove_participant(&bot, &db, &game, user)
+            .await?
+        {
+            Some(report) => {
                 bot.edit_message_text(
                     message.chat.id,
                     message.id,
-                    "Введите список желаний, я его передам Санте",
+                    format!(
+                        "Ты вышел из игры «{}». Если передумаешь, нажми /start",
+                        html::escape(&game.name)
+                    ),
                 )
                 .await?;
+                bot.send_message(message.chat.id, "До встречи на следующем празднике!")
+                    .reply_markup(KeyboardRemove::new())
+                    .await?;
+                notify_admins(&bot, &db, &game, report).await?;
             }
-        }
-        _ => {
-            log::warn!("Unrecognized callback query: {:?}", &query);
-            bot.send_message(query.message.unwrap().chat.id, "Выберите город из меню.")
+            None => {
+                bot.send_message(
+                    message.chat.id,
+                    "Не получилось выйти из игры, попробуй позже",
+                )
                 .await?;
+            }
         }
+    } else {
+        log::warn!("Unrecognized callback query: {:?}", &query);
+        bot.send_message(message.chat.id, "Выберите город из меню.")
+            .await?;
     }
 
     Ok(())
 }
 
-async fn select_city(
-    db: DatabaseHandler,
+/// Участник игры, в которой нажата кнопка. Если его в этой игре нет, бот сообщает об этом.
+async fn callback_user(
+    db: &DatabaseHandler,
     bot: &SantaBot,
-    query: CallbackQuery,
-    city: &str,
-) -> ResponseResult<()> {
-    bot.answer_callback_query(query.id).await.unwrap();
-    let message = query.message.unwrap();
-    let text = format!(include_str!("../templates/state_4_wait_notify.txt"), city);
-    bot.edit_message_text(message.chat.id, message.id, text)
+    message: &Message,
+    game_id: i64,
+    user_id: i64,
+) -> ResponseResult<Option<User>> {
+    let user = db.get_user(game_id, user_id).await;
+    if user.is_none() {
+        bot.send_message(
+            message.chat.id,
+            "Ты не участвуешь в игре, к которой относится эта кнопка. Чтобы присоединиться, нажми /start",
+        )
         .await?;
-    let keyboard =
-        KeyboardMarkup::new([[KeyboardButton::new(CHANGE_WISH_LIST)]]).resize_keyboard(true);
+    }
+    Ok(user)
+}
 
-    let url_state_1 =
-        "https://i.pinimg.com/originals/23/c7/74/23c774529515a93be6485e75faeeed36.gif";
-    bot.send_animation(
-        message.chat.id,
-        InputFile::url(Url::parse(url_state_1).unwrap()),
-    )
-    .disable_notification(true)
-    .await?;
-    bot.send_message(message.chat.id, "Теперь дождись, когда остальные внучата запишутся и мы торжественно распределим Тайных Сант и подопечных.")
-        .reply_markup(keyboard)
+/// Выбор группы при регистрации. Кнопки старых сообщений после регистрации не действуют,
+/// иначе они меняли бы группу и состояние уже распределенным участникам
+async fn select_group(
+    db: &DatabaseHandler,
+    bot: &SantaBot,
+    message: &Message,
+    game_id: i64,
+    user_id: i64,
+    group: Group,
+) -> ResponseResult<()> {
+    let mut user = match callback_user(db, bot, message, game_id, user_id).await? {
+        Some(user) if user.state == Option::from(State::ReceiveCity) => user,
+        Some(_) => {
+            bot.edit_message_text(
+                message.chat.id,
+                message.id,
+                "Группа уже выбрана, эта кнопка больше не действует",
+            )
+            .await?;
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
+    let text = format!(
+        include_str!("../templates/state_4_wait_notify.txt"),
+        html::escape(&group.name)
+    );
+    bot.edit_message_text(message.chat.id, message.id, text)
         .await?;
-    bot.send_message(message.chat.id, "Но ни в коем случае не раскрывай кто ты, иначе дух праздника и волшебства пропадет навсегда!").await?;
-    let url_state_1 =
-        "https://i.pinimg.com/originals/60/d3/03/60d303c9d5ed80378dfcbcc1923e8acc.gif";
-    bot.send_animation(
-        message.chat.id,
-        InputFile::url(Url::parse(url_state_1).unwrap()),
-    )
-    .disable_notification(true)
-    .await?;
-    let mut user = db.get_user(query.from.id.0 as i64).await.unwrap();
-    user.city = city.to_string();
+    send_wait_distribution(bot, message.chat.id).await?;
+    user.city = group.name;
+    user.group_id = Some(group.id);
     user.state = Option::from(State::Finish);
     db.save_user(user).await;
     Ok(())
@@ -3,11 +3,14 @@ use teloxide::payloads::SendAnimationSetters;
 use teloxide::prelude::{Message, Requester, ResponseResult};
 use teloxide::types::InputFile;
 use teloxide::utils::command::BotCommands;
+use teloxide::utils::html;
 
-use crate::bot::{Command, MyBot, State, ADMIN_ID};
+use crate::bot::{registration_closed, Command, MyBot, State};
 use crate::db::DatabaseHandler;
+use crate::hendlers::authorization;
+use crate::hendlers::authorization::Access;
 use crate::types::User;
-use crate::SantaBot;
+use crate::{relay, SantaBot};
 
 pub async fn handle_command(
     db: DatabaseHandler,
@@ -15,15 +18,48 @@ pub async fn handle_command(
     msg: Message,
 ) -> ResponseResult<()> {
     let my_bot = MyBot::new().await;
-    let mut user = get_user(&db, &msg).await;
+    let game_id = db.get_active_game(msg.chat.id.0).await;
+    let mut game = db.get_game(game_id).await.unwrap_or_default();
+    let mut user = get_user(&db, game_id, &msg).await;
+    let access = authorization::access(&db, &game, user.id).await;
 
-    match BotCommands::parse(msg.text().unwrap(), "") {
-        Ok(Command::Start) => {
-            if user.wish_text != "" {
+    let command = BotCommands::parse(msg.text().unwrap(), "");
+    if let Ok(command) = &command {
+        if !authorization::authorize(&game, user.id, access, command) {
+            bot.send_message(
+                msg.chat.id,
+                "Эта команда доступна только организаторам игры",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+    match command {
+        Ok(Command::Start(join_code)) => {
+            if !join_code.trim().is_empty() {
+                game = match my_bot.join_game(&bot, &msg, &db, join_code.trim()).await? {
+                    Some(game) => game,
+                    None => return Ok(()),
+                };
+                user = get_user(&db, game.id, &msg).await;
+            }
+            if user.wish_text != "" && user.state != Option::from(State::Left) {
                 bot.send_message(msg.chat.id, "Хитрец! Больше одного подарка не положено")
                     .await?;
                 return Ok(());
             }
+            if registration_closed(&db, game.id).await {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "Регистрация в игре «{}» уже закрыта, дедушка больше не принимает заявки. \
+                         Если очень хочется поучаствовать, напиши организатору",
+                        html::escape(&game.name)
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
             user.state = Option::from(State::ReceiveName);
             db.save_user(user).await;
 
@@ -44,20 +80,97 @@ pub async fn handle_command(
 
             my_bot.send_start(bot, msg).await?;
         }
+        Ok(Command::NewGame(args)) => {
+            my_bot.new_game(&bot, &msg, &db, args).await?;
+        }
+        Ok(Command::Game(args)) => {
+            my_bot.switch_game(&bot, &msg, &db, args).await?;
+        }
+        Ok(Command::Games) => {
+            my_bot.send_games(&bot, &msg, &db, &game).await?;
+        }
+        Ok(Command::Invite(args)) => {
+            my_bot.invite(&bot, &msg, &db, &game, args).await?;
+        }
+        Ok(Command::Admins) => {
+            my_bot.send_admins(&bot, &msg, &db, &game).await?;
+        }
+        Ok(Command::GrantAdmin(args)) => {
+            my_bot.grant_admin(&bot, &msg, &db, &game, args).await?;
+        }
+        Ok(Command::RevokeAdmin(args)) => {
+            my_bot.revoke_admin(&bot, &msg, &db, &game, args).await?;
+        }
         Ok(Command::List) => {
-            if user.chat_id == ADMIN_ID {
-                my_bot.send_list_users(&bot, &msg, &db).await?;
-            }
+            my_bot.send_list_users(&bot, &msg, &db, &game).await?;
         }
         Ok(Command::Notify) => {
-            if user.chat_id == ADMIN_ID {
-                my_bot.notify(&bot, &db).await?;
-            }
+            my_bot.notify(&bot, &db, &game).await?;
         }
-        Ok(Command::Distribute) => {
-            if user.chat_id == ADMIN_ID {
-                my_bot.distribute(&bot, &msg, &db).await?;
-            }
+        Ok(Command::Distribute(args)) => {
+            my_bot.distribute(&bot, &msg, &db, &game, args).await?;
+        }
+        Ok(Command::UndoDistribution(args)) => {
+            my_bot
+                .undo_distribution(&bot, &msg, &db, &game, args)
+                .await?;
+        }
+        Ok(Command::AddLate) => {
+            my_bot.add_late_participants(&bot, &msg, &db, &game).await?;
+        }
+        Ok(Command::DistributePreview(args)) => {
+            my_bot
+                .distribute_preview(&bot, &msg, &db, &game, args)
+                .await?;
+        }
+        Ok(Command::Commit) => {
+            my_bot.commit(&bot, &msg, &db, &game).await?;
+        }
+        Ok(Command::Reveal) => {
+            my_bot.reveal(&bot, &msg, &db, &game).await?;
+        }
+        Ok(Command::VerifyDraw) => {
+            my_bot
+                .verify_draw(&bot, &msg, &db, &game, access >= Access::Admin)
+                .await?;
+        }
+        Ok(Command::Schedule(args)) => {
+            my_bot.schedule(&bot, &msg, &db, &game, args).await?;
+        }
+        Ok(Command::Unschedule(args)) => {
+            my_bot.unschedule(&bot, &msg, &db, &game, args).await?;
+        }
+        Ok(Command::Groups) => {
+            my_bot.send_groups(&bot, &msg, &db, &game).await?;
+        }
+        Ok(Command::AddGroup(args)) => {
+            my_bot.add_group(&bot, &msg, &db, &game, args).await?;
+        }
+        Ok(Command::RemoveGroup(args)) => {
+            my_bot.remove_group(&bot, &msg, &db, &game, args).await?;
+        }
+        Ok(Command::Exclude(args)) => {
+            my_bot.add_exclusion(&bot, &msg, &db, &game, args).await?;
+        }
+        Ok(Command::Exclusions) => {
+            my_bot.send_exclusions(&bot, &msg, &db, &game).await?;
+        }
+        Ok(Command::Unexclude(args)) => {
+            my_bot
+                .remove_exclusion(&bot, &msg, &db, &game, args)
+                .await?;
+        }
+        Ok(Command::Check) => {
+            my_bot.check(&bot, &msg, &db, &game).await?;
+        }
+        Ok(Command::Leave) => {
+            my_bot.ask_leave(&bot, &msg, &user).await?;
+        }
+        Ok(Command::Delete) => {
+            relay::delete_for_both(&bot, &db, &msg).await?;
+        }
+        Ok(Command::RemoveUser(args)) => {
+            my_bot.remove_user(&bot, &msg, &db, &game, args).await?;
         }
         Ok(Command::Help) => {
             my_bot.send_help(bot, msg).await?;
@@ -67,7 +180,7 @@ pub async fn handle_command(
     return Ok(());
 }
 
-async fn get_user(db: &DatabaseHandler, msg: &Message) -> User {
+async fn get_user(db: &DatabaseHandler, game_id: i64, msg: &Message) -> User {
     let user_id = msg.chat.id.0;
     let name = msg
         .chat
@@ -75,8 +188,8 @@ async fn get_user(db: &DatabaseHandler, msg: &Message) -> User {
         .or(msg.chat.first_name())
         .unwrap_or(user_id.to_string().as_str())
         .to_string();
-    db.get_user(user_id)
+    db.get_user(game_id, user_id)
         .await
-        .or(User::default_user(user_id, name))
+        .or(User::default_user(game_id, user_id, name))
         .unwrap()
 }
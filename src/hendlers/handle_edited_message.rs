@@ -0,0 +1,13 @@
+use crate::db::DatabaseHandler;
+use crate::relay;
+use crate::SantaBot;
+use teloxide::prelude::*;
+
+/// Правки сообщений из переписки Санты и подопечного переносятся собеседнику
+pub async fn handle_edited_message(
+    db: DatabaseHandler,
+    bot: SantaBot,
+    msg: Message,
+) -> ResponseResult<()> {
+    relay::relay_edit(&bot, &db, &msg).await
+}
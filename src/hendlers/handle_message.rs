@@ -1,7 +1,8 @@
+use crate::bot::{registration_closed, State};
 use crate::db::DatabaseHandler;
 use crate::states::state_factory;
 use crate::types::User;
-use crate::SantaBot;
+use crate::{relay, SantaBot};
 use teloxide::prelude::*;
 
 pub async fn handle_message(
@@ -9,13 +10,29 @@ pub async fn handle_message(
     bot: SantaBot,
     msg: Message,
 ) -> ResponseResult<()> {
-    let user = get_user(&db, &msg).await;
+    if relay::route_reply(&bot, &db, &msg).await? {
+        return Ok(());
+    }
+    let game_id = db.get_active_game(msg.chat.id.0).await;
+    let user = get_user(&db, game_id, &msg).await;
+    if !user.is_registered()
+        && user.state != Option::from(State::Left)
+        && registration_closed(&db, game_id).await
+    {
+        bot.send_message(
+            msg.chat.id,
+            "Прости, внучок, регистрация уже закрыта и дедушка больше не принимает заявки. \
+             Если очень хочется поучаствовать, напиши организатору",
+        )
+        .await?;
+        return Ok(());
+    }
     let state = state_factory(&user.state);
     state.as_ref().handle(user, msg, bot, db).await;
     Ok(())
 }
 
-async fn get_user(db: &DatabaseHandler, msg: &Message) -> User {
+async fn get_user(db: &DatabaseHandler, game_id: i64, msg: &Message) -> User {
     let user_id = msg.chat.id.0;
     let name = msg
         .chat
@@ -23,8 +40,8 @@ async fn get_user(db: &DatabaseHandler, msg: &Message) -> User {
         .or(msg.chat.first_name())
         .unwrap_or(user_id.to_string().as_str())
         .to_string();
-    db.get_user(user_id)
+    db.get_user(game_id, user_id)
         .await
-        .or(User::default_user(user_id, name))
+        .or(User::default_user(game_id, user_id, name))
         .unwrap()
 }
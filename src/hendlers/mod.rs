@@ -1,3 +1,5 @@
+pub mod authorization;
 pub mod handle_callback_query;
 pub mod handle_command;
+pub mod handle_edited_message;
 pub mod handle_message;
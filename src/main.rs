@@ -11,13 +11,19 @@ use teloxide::{prelude::*, update_listeners::webhooks};
 use crate::db::DatabaseHandler;
 use crate::hendlers::handle_callback_query::handle_callback_query;
 use crate::hendlers::handle_command::handle_command;
+use crate::hendlers::handle_edited_message::handle_edited_message;
 use crate::hendlers::handle_message::handle_message;
 
 mod bot;
+mod budget;
 mod db;
+mod distribution;
 mod hendlers;
+mod relay;
+mod scheduler;
 mod states;
 mod types;
+mod validation;
 
 pub type SantaBot = DefaultParseMode<Trace<Bot>>;
 
@@ -37,6 +43,7 @@ async fn build_dispatcher() {
     let bot = Bot::from_env()
         .trace(Settings::TRACE_EVERYTHING_VERBOSE)
         .parse_mode(teloxide::types::ParseMode::Html);
+    tokio::spawn(scheduler::run(bot.clone(), db.clone()));
 
     return Dispatcher::builder(
         bot,
@@ -47,6 +54,7 @@ async fn build_dispatcher() {
                     .endpoint(handle_command),
             )
             .branch(Update::filter_message().endpoint(handle_message))
+            .branch(Update::filter_edited_message().endpoint(handle_edited_message))
             .branch(Update::filter_callback_query().endpoint(handle_callback_query)),
     )
         .dependencies(dptree::deps![db])
@@ -63,6 +71,7 @@ async fn build_dispatcher() {
     let bot = Bot::from_env()
         .trace(Settings::TRACE_EVERYTHING_VERBOSE)
         .parse_mode(teloxide::types::ParseMode::Html);
+    tokio::spawn(scheduler::run(bot.clone(), db.clone()));
 
     let listener = get_listener(bot.clone()).await;
 
@@ -75,6 +84,7 @@ async fn build_dispatcher() {
                     .endpoint(handle_command),
             )
             .branch(Update::filter_message().endpoint(handle_message))
+            .branch(Update::filter_edited_message().endpoint(handle_edited_message))
             .branch(Update::filter_callback_query().endpoint(handle_callback_query)),
     )
         .dependencies(dptree::deps![db])
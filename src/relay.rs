@@ -0,0 +1,556 @@
+use chrono::Utc;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile, MessageId};
+use teloxide::utils::html;
+
+use crate::bot::{
+    callback_data, format_schedule_time, HISTORY_CALLBACK_PREFIX, KEY_CHILD_CHAT, KEY_CHILD_CHAT_CLOSE,
+    KEY_SANTA_CHAT, KEY_SANTA_CHAT_CLOSE, REPLY_CALLBACK, SEND_CALLBACK_PREFIX,
+};
+use crate::db::DatabaseHandler;
+use crate::types::{MessageKind, User};
+use crate::{types, SantaBot};
+
+/// Сколько сообщений переписки показывается на одной странице
+const HISTORY_PAGE_SIZE: u64 = 10;
+/// Длинные сообщения в истории обрезаются, чтобы страница поместилась в одно сообщение Telegram
+const MAX_ENTRY_LEN: usize = 350;
+
+/// Тип и `file_id` вложения; у фото берется самый большой размер
+pub fn media_of(msg: &Message) -> Option<(MessageKind, String)> {
+    if let Some(photo) = msg.photo().and_then(|sizes| sizes.last()) {
+        return Some((MessageKind::Photo, photo.file.id.clone()));
+    }
+    if let Some(sticker) = msg.sticker() {
+        return Some((MessageKind::Sticker, sticker.file.id.clone()));
+    }
+    if let Some(voice) = msg.voice() {
+        return Some((MessageKind::Voice, voice.file.id.clone()));
+    }
+    if let Some(animation) = msg.animation() {
+        return Some((MessageKind::Animation, animation.file.id.clone()));
+    }
+    if let Some(document) = msg.document() {
+        return Some((MessageKind::Document, document.file.id.clone()));
+    }
+    if let Some(video) = msg.video() {
+        return Some((MessageKind::Video, video.file.id.clone()));
+    }
+    if let Some(audio) = msg.audio() {
+        return Some((MessageKind::Audio, audio.file.id.clone()));
+    }
+    if let Some(video_note) = msg.video_note() {
+        return Some((MessageKind::VideoNote, video_note.file.id.clone()));
+    }
+    None
+}
+
+/// Анонимно пересылает сообщение собеседнику и сохраняет его в переписку.
+/// Вложения пересылаются через `copy_message`, он не показывает отправителя.
+/// Ответ на сообщение переписки (`reply_to` — его id в чате отправителя) пересылается
+/// ответом на его копию у собеседника. Под копией появляется кнопка «Ответить».
+pub async fn relay(
+    bot: &SantaBot,
+    db: &DatabaseHandler,
+    msg: &Message,
+    user: &User,
+    from_santa: bool,
+    reply_to: Option<i32>,
+) -> ResponseResult<()> {
+    let (kind, text, file_id) = match (msg.text(), media_of(msg)) {
+        (Some(text), _) => (MessageKind::Text, Some(text.to_string()), None),
+        (None, Some((kind, file_id))) => {
+            (kind, msg.caption().map(|caption| caption.to_string()), Some(file_id))
+        }
+        (None, None) => {
+            bot.send_message(msg.chat.id, "Такое сообщение я переслать не могу")
+                .await?;
+            return Ok(());
+        }
+    };
+    let (santa_id, child_id, recipient) = match from_santa {
+        true => {
+            let child_id = user.child.unwrap();
+            (user.id, child_id, child_id)
+        }
+        false => {
+            let santa_id = user.santa.unwrap();
+            (santa_id, user.id, santa_id)
+        }
+    };
+    let header = header(from_santa);
+
+    let reply_to = match reply_to {
+        Some(reply_to) => db
+            .find_chat_message_in_chat(user.id, reply_to)
+            .await
+            .filter(|message| {
+                message.game_id == user.game_id
+                    && message.santa_id == santa_id
+                    && message.child_id == child_id
+            })
+            .and_then(|message| match message.from_santa == from_santa {
+                true => message.recipient_message_id,
+                false => message.sender_message_id,
+            })
+            .map(MessageId),
+        None => None,
+    };
+
+    let (header_message_id, recipient_message_id) = match kind {
+        MessageKind::Text => {
+            let mut request = bot
+                .send_message(
+                    ChatId(recipient),
+                    format!("{}\n{}", header, html::escape(text.as_deref().unwrap_or_default())),
+                )
+                .reply_markup(reply_keyboard(user.game_id));
+            if let Some(reply_to) = reply_to {
+                request = request
+                    .reply_to_message_id(reply_to)
+                    .allow_sending_without_reply(true);
+            }
+            (None, request.await?.id)
+        }
+        _ => {
+            let header_message = bot.send_message(ChatId(recipient), header).await?;
+            let mut request = bot
+                .copy_message(ChatId(recipient), msg.chat.id, msg.id)
+                .reply_markup(reply_keyboard(user.game_id));
+            if let Some(reply_to) = reply_to {
+                request = request
+                    .reply_to_message_id(reply_to)
+                    .allow_sending_without_reply(true);
+            }
+            (Some(header_message.id.0), request.await?)
+        }
+    };
+    db.save_message(types::Message {
+        id: 0,
+        game_id: user.game_id,
+        santa_id,
+        child_id,
+        from_santa,
+        kind,
+        text,
+        file_id,
+        sender_message_id: Some(msg.id.0),
+        recipient_message_id: Some(recipient_message_id.0),
+        header_message_id,
+        create_date: DateTimeWithTimeZone::from(Utc::now()),
+    })
+    .await;
+    Ok(())
+}
+
+/// Пересылает сообщение в ту переписку, к которой оно относится, независимо от состояния
+/// пользователя и от активной игры: после кнопки «Ответить» или ответом Telegram
+/// на сообщение переписки. Возвращает `false`, если сообщение ни к какой переписке не относится.
+pub async fn route_reply(
+    bot: &SantaBot,
+    db: &DatabaseHandler,
+    msg: &Message,
+) -> ResponseResult<bool> {
+    let chat_id = msg.chat.id.0;
+    let quick_reply = db.take_reply_to(chat_id).await;
+    // Кнопки меню отменяют начатый ответ
+    if matches!(
+        msg.text(),
+        Some(KEY_CHILD_CHAT | KEY_SANTA_CHAT | KEY_CHILD_CHAT_CLOSE | KEY_SANTA_CHAT_CLOSE)
+    ) {
+        return Ok(false);
+    }
+    let reply_to = match quick_reply.or(msg.reply_to_message().map(|reply| reply.id.0)) {
+        Some(reply_to) => reply_to,
+        None => return Ok(false),
+    };
+    let message = match db.find_chat_message_in_chat(chat_id, reply_to).await {
+        Some(message) => message,
+        None => return Ok(false),
+    };
+    let from_santa = message.santa_id == chat_id;
+    // После отмены распределения пара могла распасться, тогда переписки больше нет
+    let user = match db.get_user(message.game_id, chat_id).await {
+        Some(user) if from_santa && user.child == Some(message.child_id) => user,
+        Some(user) if !from_santa && user.santa == Some(message.santa_id) => user,
+        _ => return Ok(false),
+    };
+    relay(bot, db, msg, &user, from_santa, Some(reply_to)).await?;
+    if quick_reply.is_some() {
+        bot.send_message(msg.chat.id, format!("Ответ отправлен {}", addressee(from_santa)))
+            .await?;
+    }
+    Ok(true)
+}
+
+/// Кнопка «Ответить» под сообщением игры `game_id`: следующее сообщение пользователя
+/// уйдет автору `message`
+pub async fn start_quick_reply(
+    bot: &SantaBot,
+    db: &DatabaseHandler,
+    game_id: i64,
+    message: &Message,
+) -> ResponseResult<()> {
+    let chat_id = message.chat.id.0;
+    let chat_message = db
+        .find_chat_message_in_chat(chat_id, message.id.0)
+        .await
+        .filter(|chat_message| chat_message.game_id == game_id);
+    let from_santa = match chat_message {
+        Some(chat_message) => chat_message.santa_id == chat_id,
+        None => {
+            bot.send_message(message.chat.id, "Эта переписка больше недоступна")
+                .await?;
+            return Ok(());
+        }
+    };
+    db.set_reply_to(chat_id, game_id, message.id.0).await;
+    bot.send_message(
+        message.chat.id,
+        format!("Напиши ответ, я передам его {}", addressee(from_santa)),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Сообщение вне беседы: бот спрашивает, кому его отправить, а не теряет его.
+/// Вопрос отправляется ответом на сообщение, по нему кнопка находит, что пересылать.
+pub async fn ask_recipient(bot: &SantaBot, msg: &Message, user: &User) -> ResponseResult<()> {
+    let mut buttons = vec![];
+    if user.child.is_some() {
+        buttons.push(InlineKeyboardButton::callback(
+            "Подопечному",
+            callback_data(SEND_CALLBACK_PREFIX, user.game_id, "child"),
+        ));
+    }
+    if user.santa.is_some() {
+        buttons.push(InlineKeyboardButton::callback(
+            "Санте",
+            callback_data(SEND_CALLBACK_PREFIX, user.game_id, "santa"),
+        ));
+    }
+    if buttons.is_empty() {
+        return Ok(());
+    }
+    bot.send_message(msg.chat.id, "Кому отправить это сообщение?")
+        .reply_to_message_id(msg.id)
+        .reply_markup(InlineKeyboardMarkup::new([buttons]))
+        .await?;
+    Ok(())
+}
+
+/// Кнопка из [`ask_recipient`]: пересылает исходное сообщение выбранному собеседнику
+pub async fn send_to(
+    bot: &SantaBot,
+    db: &DatabaseHandler,
+    user: &User,
+    args: &str,
+    prompt: &Message,
+) -> ResponseResult<()> {
+    let from_santa = match args {
+        "child" if user.child.is_some() => true,
+        "santa" if user.santa.is_some() => false,
+        _ => return Ok(()),
+    };
+    let text = match prompt.reply_to_message() {
+        Some(original) => {
+            relay(bot, db, original, user, from_santa, None).await?;
+            format!("Отправлено {}", addressee(from_santa))
+        }
+        None => "Не нашел сообщение, отправь его еще раз".to_string(),
+    };
+    bot.edit_message_text(prompt.chat.id, prompt.id, text).await?;
+    Ok(())
+}
+
+/// Кому уходит сообщение, в дательном падеже
+fn addressee(from_santa: bool) -> &'static str {
+    match from_santa {
+        true => "подопечному",
+        false => "Санте",
+    }
+}
+
+/// Переносит правку сообщения на его копию у собеседника и в сохраненную переписку.
+/// Правки сообщений, которые не пересылались собеседнику, пропускаются.
+pub async fn relay_edit(bot: &SantaBot, db: &DatabaseHandler, msg: &Message) -> ResponseResult<()> {
+    let message = match own_message(db, msg.chat.id.0, msg.id.0).await {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let (recipient, copy_id) = match counterpart(&message) {
+        Some(counterpart) => counterpart,
+        None => return Ok(()),
+    };
+    let text = match message.kind {
+        MessageKind::Text => {
+            let text = msg.text().unwrap_or_default();
+            bot.edit_message_text(
+                recipient,
+                copy_id,
+                format!("{}\n{}", header(message.from_santa), html::escape(text)),
+            )
+            .reply_markup(reply_keyboard(message.game_id))
+            .await?;
+            Some(text.to_string())
+        }
+        // У стикеров и видеосообщений нет подписи, редактировать нечего
+        MessageKind::Sticker | MessageKind::VideoNote => return Ok(()),
+        _ => {
+            let caption = msg.caption().map(|caption| caption.to_string());
+            let mut request = bot
+                .edit_message_caption(recipient, copy_id)
+                .reply_markup(reply_keyboard(message.game_id));
+            if let Some(caption) = &caption {
+                request = request.caption(html::escape(caption));
+            }
+            request.await?;
+            caption
+        }
+    };
+    db.update_chat_message_text(message.id, text).await;
+    Ok(())
+}
+
+/// Удаляет свое сообщение переписки у обоих собеседников: команда отправляется ответом на него
+pub async fn delete_for_both(
+    bot: &SantaBot,
+    db: &DatabaseHandler,
+    msg: &Message,
+) -> ResponseResult<()> {
+    let message = match msg.reply_to_message() {
+        Some(reply) => own_message(db, msg.chat.id.0, reply.id.0).await,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "Ответь командой /delete на свое сообщение, которое нужно удалить",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let message = match message {
+        Some(message) => message,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "Удалить можно только свои сообщения из переписки с Сантой или подопечным",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    // Telegram разрешает ботам удалять только сообщения младше 48 часов
+    if let Some((recipient, copy_id)) = counterpart(&message) {
+        if let Err(err) = bot.delete_message(recipient, copy_id).await {
+            log::warn!("Could not delete relayed message {}: {:?}", message.id, err);
+            bot.send_message(
+                msg.chat.id,
+                "Не получилось удалить сообщение у собеседника: Telegram позволяет удалять только сообщения младше 48 часов",
+            )
+            .await?;
+            return Ok(());
+        }
+        if let Some(header_message_id) = message.header_message_id {
+            bot.delete_message(recipient, MessageId(header_message_id))
+                .await
+                .ok();
+        }
+    }
+    db.delete_chat_message(message.id).await;
+    if let Some(sender_message_id) = message.sender_message_id {
+        bot.delete_message(msg.chat.id, MessageId(sender_message_id))
+            .await
+            .ok();
+    }
+    bot.delete_message(msg.chat.id, msg.id).await.ok();
+    Ok(())
+}
+
+/// Заголовок пересланного сообщения у собеседника
+fn header(from_santa: bool) -> &'static str {
+    match from_santa {
+        true => "У вас новое сообщение от Санты:",
+        false => "У вас новое сообщение от подопечного:",
+    }
+}
+
+/// Кнопка «Ответить» под пересланным сообщением игры `game_id`
+fn reply_keyboard(game_id: i64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+        "↩️ Ответить",
+        callback_data(REPLY_CALLBACK, game_id, ""),
+    )]])
+}
+
+/// Сообщение, которое пользователь сам отправил в переписку с подопечным или с Сантой
+/// в любой из своих игр
+async fn own_message(
+    db: &DatabaseHandler,
+    user_id: i64,
+    message_id: i32,
+) -> Option<types::Message> {
+    db.find_chat_message_in_chat(user_id, message_id)
+        .await
+        .filter(|message| message.from_santa == (message.santa_id == user_id))
+}
+
+/// Чат собеседника и id копии сообщения в нем
+fn counterpart(message: &types::Message) -> Option<(ChatId, MessageId)> {
+    let recipient = match message.from_santa {
+        true => message.child_id,
+        false => message.santa_id,
+    };
+    Some((ChatId(recipient), MessageId(message.recipient_message_id?)))
+}
+
+/// Страница истории переписки, готовая к отправке
+pub struct HistoryPage {
+    pub text: String,
+    pub keyboard: InlineKeyboardMarkup,
+    /// Сообщения страницы, по ним повторно отправляются вложения
+    pub messages: Vec<types::Message>,
+}
+
+/// Страница переписки пользователя с подопечным (`with_child`) или с Сантой.
+/// Страница 0 — самые новые сообщения, кнопки листают к более старым и обратно.
+pub async fn history_page(
+    db: &DatabaseHandler,
+    user: &User,
+    with_child: bool,
+    page: u64,
+) -> Option<HistoryPage> {
+    let (santa_id, child_id, santa_label, child_label) = match with_child {
+        true => (user.id, user.child?, "Вы", "Подопечный"),
+        false => (user.santa?, user.id, "Санта", "Вы"),
+    };
+    let (messages, pages) = db
+        .get_chat_page(user.game_id, santa_id, child_id, page, HISTORY_PAGE_SIZE)
+        .await;
+    if messages.is_empty() {
+        return None;
+    }
+
+    let mut entries: Vec<String> = messages
+        .iter()
+        .map(|message| {
+            let author = match message.from_santa {
+                true => santa_label,
+                false => child_label,
+            };
+            format!(
+                "<i>{}</i> <b>{}:</b>\n{}",
+                format_schedule_time(&message.create_date),
+                author,
+                html::escape(&entry_text(message)),
+            )
+        })
+        .collect();
+    if pages > 1 {
+        entries.insert(0, format!("<i>Страница {} из {}</i>", pages - page, pages));
+    }
+
+    let chat = match with_child {
+        true => "child",
+        false => "santa",
+    };
+    let mut buttons = vec![];
+    if page + 1 < pages {
+        buttons.push(InlineKeyboardButton::callback(
+            "⬅️ Раньше",
+            callback_data(HISTORY_CALLBACK_PREFIX, user.game_id, format!("{}:{}", chat, page + 1)),
+        ));
+    }
+    if page > 0 {
+        buttons.push(InlineKeyboardButton::callback(
+            "Позже ➡️",
+            callback_data(HISTORY_CALLBACK_PREFIX, user.game_id, format!("{}:{}", chat, page - 1)),
+        ));
+    }
+
+    Some(HistoryPage {
+        text: entries.join("\n\n"),
+        keyboard: InlineKeyboardMarkup::new([buttons]),
+        messages,
+    })
+}
+
+/// Разбирает аргументы кнопки листания истории: (переписка с подопечным, страница)
+pub fn parse_history_callback(args: &str) -> Option<(bool, u64)> {
+    let (chat, page) = args.split_once(':')?;
+    let with_child = match chat {
+        "child" => true,
+        "santa" => false,
+        _ => return None,
+    };
+    Some((with_child, page.parse().ok()?))
+}
+
+/// Текст сообщения для истории; вложения показываются пометкой
+fn entry_text(message: &types::Message) -> String {
+    let text = match (&message.kind, &message.text) {
+        (MessageKind::Text, text) => text.clone().unwrap_or_default(),
+        (kind, Some(caption)) => format!("[{}] {}", kind.label(), caption),
+        (kind, None) => format!("[{}]", kind.label()),
+    };
+    match text.chars().count() > MAX_ENTRY_LEN {
+        true => format!("{}…", text.chars().take(MAX_ENTRY_LEN).collect::<String>()),
+        false => text,
+    }
+}
+
+/// Повторно отправляет вложения переписки; `author` подписывает каждое вложение
+pub async fn replay_media<F>(
+    bot: &SantaBot,
+    chat_id: ChatId,
+    messages: &[types::Message],
+    author: F,
+) -> ResponseResult<()>
+where
+    F: Fn(&types::Message) -> &'static str,
+{
+    for item in messages.iter() {
+        let file_id = match (&item.kind, &item.file_id) {
+            (MessageKind::Text, _) | (_, None) => continue,
+            (_, Some(file_id)) => file_id,
+        };
+        let caption = match &item.text {
+            Some(caption) => format!("<b>{}:</b> {}", author(item), html::escape(caption)),
+            None => format!("<b>{}</b>", author(item)),
+        };
+        let file = InputFile::file_id(file_id.clone());
+        match item.kind {
+            MessageKind::Text => {}
+            MessageKind::Photo => {
+                bot.send_photo(chat_id, file).caption(caption).await?;
+            }
+            MessageKind::Voice => {
+                bot.send_voice(chat_id, file).caption(caption).await?;
+            }
+            MessageKind::Document => {
+                bot.send_document(chat_id, file).caption(caption).await?;
+            }
+            MessageKind::Video => {
+                bot.send_video(chat_id, file).caption(caption).await?;
+            }
+            MessageKind::Audio => {
+                bot.send_audio(chat_id, file).caption(caption).await?;
+            }
+            MessageKind::Animation => {
+                bot.send_animation(chat_id, file).caption(caption).await?;
+            }
+            // У стикеров и видеосообщений нет подписи, автор отправляется отдельным сообщением
+            MessageKind::Sticker => {
+                bot.send_message(chat_id, caption).await?;
+                bot.send_sticker(chat_id, file).await?;
+            }
+            MessageKind::VideoNote => {
+                bot.send_message(chat_id, caption).await?;
+                bot.send_video_note(chat_id, file).await?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use teloxide::prelude::*;
+use teloxide::utils::html;
+
+use crate::bot::{notify_admins, MyBot};
+use crate::db::DatabaseHandler;
+use crate::types::{GameState, Schedule, ScheduledAction};
+use crate::SantaBot;
+
+/// Как часто бот проверяет, не наступило ли время запланированных действий
+const TICK: Duration = Duration::from_secs(60);
+/// После стольких ошибок подряд действие снимается с расписания, а организаторы получают сообщение
+const MAX_ATTEMPTS: i32 = 3;
+
+/// Выполняет запланированные действия всех игр. Расписание хранится в БД, а действие
+/// отмечается выполненным только после успеха, поэтому после перезапуска бот выполнит
+/// и действия, время которых наступило, пока он не работал, и прерванные сбоем.
+pub async fn run(bot: SantaBot, db: DatabaseHandler) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        let now = DateTimeWithTimeZone::from(Utc::now());
+        for schedule in db.get_due_schedules(now).await {
+            match execute(&bot, &db, &schedule).await {
+                Ok(()) => db.mark_schedule_done(schedule.id, now).await,
+                Err(err) => {
+                    log::error!(
+                        "Scheduled {} in game {} failed: {:?}",
+                        schedule.action,
+                        schedule.game_id,
+                        err
+                    );
+                    give_up_or_retry(&bot, &db, &schedule, now).await;
+                }
+            }
+        }
+    }
+}
+
+/// Ошибку Telegram посередине действия бот повторяет в следующих тактах, но не бесконечно
+async fn give_up_or_retry(
+    bot: &SantaBot,
+    db: &DatabaseHandler,
+    schedule: &Schedule,
+    now: DateTimeWithTimeZone,
+) {
+    let attempts = schedule.attempts + 1;
+    db.set_schedule_attempts(schedule.id, attempts).await;
+    if attempts < MAX_ATTEMPTS {
+        return;
+    }
+    db.mark_schedule_done(schedule.id, now).await;
+    if let Some(game) = db.get_game(schedule.game_id).await {
+        let report = format!(
+            "Игра «{}»: запланированное действие {} не удалось выполнить за {} попытки, выполните его вручную",
+            html::escape(&game.name),
+            schedule.action,
+            MAX_ATTEMPTS
+        );
+        if let Err(err) = notify_admins(bot, db, &game, report).await {
+            log::error!("Could not notify admins of game {}: {:?}", game.id, err);
+        }
+    }
+}
+
+async fn execute(bot: &SantaBot, db: &DatabaseHandler, schedule: &Schedule) -> ResponseResult<()> {
+    let game = match db.get_game(schedule.game_id).await {
+        Some(game) => game,
+        None => return Ok(()),
+    };
+    let my_bot = MyBot::new().await;
+    let report = match schedule.action {
+        ScheduledAction::CloseRegistration => {
+            let registered = db
+                .get_all_users(game.id)
+                .await
+                .iter()
+                .filter(|user| user.is_registered())
+                .count();
+            format!("регистрация закрыта, участников: {}", registered)
+        }
+        ScheduledAction::Distribute => {
+            let has_groups = !db.get_groups(game.id).await.is_empty();
+            match MyBot::parse_distribution_args(&schedule.args, has_groups) {
+                Ok((mode, history_seasons)) => {
+                    my_bot
+                        .run_distribution(bot, db, &game, mode, history_seasons)
+                        .await?
+                }
+                Err(response_msg) => response_msg,
+            }
+        }
+        ScheduledAction::Notify => {
+            // Состояние читается заново: распределение могло пройти в этом же такте
+            let current = db.get_game(game.id).await.unwrap_or_else(|| game.clone());
+            if current.state == GameState::Distributed {
+                my_bot.notify(bot, db, &current).await?;
+                "участники узнали своих подопечных".to_string()
+            } else {
+                "распределения еще нет, уведомлять некого".to_string()
+            }
+        }
+        ScheduledAction::Reveal => match my_bot.reveal_draw(bot, db, &game).await? {
+            true => "seed распределения раскрыт участникам".to_string(),
+            false => "распределений еще не было, раскрывать нечего".to_string(),
+        },
+    };
+    notify_admins(
+        bot,
+        db,
+        &game,
+        format!(
+            "Игра «{}», по расписанию: {}",
+            html::escape(&game.name),
+            report
+        ),
+    )
+    .await
+}
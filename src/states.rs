@@ -1,21 +1,20 @@
 use async_trait::async_trait;
-use chrono::Utc;
 use reqwest::Url;
-use sea_orm::prelude::DateTimeWithTimeZone;
 use teloxide::prelude::Message;
 use teloxide::prelude::*;
 use teloxide::types::{
     InlineKeyboardButton, InlineKeyboardMarkup, InputFile, KeyboardButton, KeyboardMarkup,
 };
+use teloxide::utils::html;
 
 use crate::bot::{
-    State, CHANGE_WISH_CALLBACK, CHANGE_WISH_LIST, CHILD_PATTERN, CITY_CALLBACK_IZH,
-    CITY_CALLBACK_MSK, IZHEVSK_CITY, KEY_CHILD_CHAT, KEY_CHILD_CHAT_CLOSE, KEY_SANTA_CHAT,
-    KEY_SANTA_CHAT_CLOSE, MOSCOW_CITY, SANTA_PATTERN,
+    callback_data, send_wait_distribution, State, CHANGE_WISH_CALLBACK, CHANGE_WISH_LIST,
+    GROUP_CALLBACK_PREFIX, KEY_CHILD_CHAT, KEY_CHILD_CHAT_CLOSE, KEY_SANTA_CHAT,
+    KEY_SANTA_CHAT_CLOSE,
 };
 use crate::db::DatabaseHandler;
 use crate::types::User;
-use crate::{types, SantaBot};
+use crate::{budget, relay, SantaBot};
 
 pub(crate) struct ReceiveNameStrategy;
 
@@ -31,6 +30,12 @@ pub(crate) struct FinishStrategy;
 
 pub(crate) struct DistributedStrategy;
 
+pub(crate) struct LeftStrategy;
+
+pub(crate) struct NotStartedStrategy;
+
+pub(crate) struct ReceiveCityStrategy;
+
 pub fn state_factory(state: &Option<State>) -> Box<dyn StateStrategy> {
     match state {
         Some(State::ReceiveName) => Box::new(ReceiveNameStrategy),
@@ -40,7 +45,10 @@ pub fn state_factory(state: &Option<State>) -> Box<dyn StateStrategy> {
         Some(State::ChangeWishList) => Box::new(ChangeWishListStrategy),
         Some(State::Finish) => Box::new(FinishStrategy),
         Some(State::Distributed) => Box::new(DistributedStrategy),
-        _ => panic!("State not found"),
+        Some(State::Left) => Box::new(LeftStrategy),
+        Some(State::ReceiveCity) => Box::new(ReceiveCityStrategy),
+        // Пользователь без регистрации в текущей игре, например после /new_game или /game
+        None | Some(State::Start) => Box::new(NotStartedStrategy),
     }
 }
 
@@ -54,53 +62,109 @@ impl StateStrategy for DistributedStrategy {
     async fn handle(&self, mut user: User, msg: Message, bot: SantaBot, db: DatabaseHandler) {
         match msg.text() {
             Some(KEY_CHILD_CHAT) => {
-                let message = match db.find_message(user.chat_id, user.child.unwrap()).await {
-                    Some(message_db) => message_db.message
-                        .replace(SANTA_PATTERN, "Вы")
-                        .replace(CHILD_PATTERN, "Подопечный"),
-                    None => String::from("У вас еще нет сообщений с подопечным, но все что ты напишешь ниже я ему покажу")
-                };
+                match relay::history_page(&db, &user, true, 0).await {
+                    Some(page) => {
+                        bot.send_message(msg.chat.id, page.text)
+                            .reply_markup(page.keyboard)
+                            .await
+                            .unwrap();
+                        relay::replay_media(&bot, msg.chat.id, &page.messages, |item| match item.from_santa {
+                            true => "Вы",
+                            false => "Подопечный",
+                        })
+                        .await
+                        .unwrap();
+                    }
+                    None => {
+                        bot.send_message(msg.chat.id, "У вас еще нет сообщений с подопечным, но все что ты напишешь ниже я ему покажу")
+                            .await
+                            .unwrap();
+                    }
+                }
 
                 let keyboard = KeyboardMarkup::new([[KeyboardButton::new(KEY_CHILD_CHAT_CLOSE)]])
                     .resize_keyboard(true);
-
-                bot.send_message(msg.chat.id, message)
+                let text = format!("<i>Все что напишете ниже я отправлю Подопечному, чтобы выйти из беседы, нажми '{}</i>'", KEY_CHILD_CHAT_CLOSE);
+                bot.send_message(msg.chat.id, text)
                     .reply_markup(keyboard)
                     .await
                     .unwrap();
 
-                let text = format!("<i>Все что напишете ниже я отправлю Подопечному, чтобы выйти из беседы, нажми '{}</i>'", KEY_CHILD_CHAT_CLOSE);
-                bot.send_message(msg.chat.id, text).await.unwrap();
-
                 user.state = Option::from(State::ChildChat);
                 db.save_user(user).await;
             }
             Some(KEY_SANTA_CHAT) => {
-                let message = match db.find_message(user.santa.unwrap(), user.chat_id).await {
-                    Some(message_db) => message_db.message
-                        .replace(SANTA_PATTERN, "Санта")
-                        .replace(CHILD_PATTERN, "Вы"),
-                    None => String::from("У вас еще нет сообщений с сантой, но все что ты напишешь ниже я ему покажу")
-                };
+                match relay::history_page(&db, &user, false, 0).await {
+                    Some(page) => {
+                        bot.send_message(msg.chat.id, page.text)
+                            .reply_markup(page.keyboard)
+                            .await
+                            .unwrap();
+                        relay::replay_media(&bot, msg.chat.id, &page.messages, |item| match item.from_santa {
+                            true => "Санта",
+                            false => "Вы",
+                        })
+                        .await
+                        .unwrap();
+                    }
+                    None => {
+                        bot.send_message(msg.chat.id, "У вас еще нет сообщений с сантой, но все что ты напишешь ниже я ему покажу")
+                            .await
+                            .unwrap();
+                    }
+                }
 
                 let keyboard = KeyboardMarkup::new([[KeyboardButton::new(KEY_SANTA_CHAT_CLOSE)]])
                     .resize_keyboard(true);
-
-                bot.send_message(msg.chat.id, message)
+                let text = format!("<i>Все что напишете ниже я отправлю Cанте, чтобы выйти из беседы, нажми '{}</i>'", KEY_SANTA_CHAT_CLOSE);
+                bot.send_message(msg.chat.id, text)
                     .reply_markup(keyboard)
                     .await
                     .unwrap();
-                let text = format!("<i>Все что напишете ниже я отправлю Cанте, чтобы выйти из беседы, нажми '{}</i>'", KEY_SANTA_CHAT_CLOSE);
-                bot.send_message(msg.chat.id, text).await.unwrap();
 
                 user.state = Option::from(State::SantaChat);
                 db.save_user(user).await;
             }
-            _ => {}
+            _ => {
+                relay::ask_recipient(&bot, &msg, &user).await.unwrap();
+            }
         }
     }
 }
 
+#[async_trait]
+impl StateStrategy for NotStartedStrategy {
+    async fn handle(&self, _user: User, msg: Message, bot: SantaBot, _db: DatabaseHandler) {
+        bot.send_message(
+            msg.chat.id,
+            "Ты еще не зарегистрирован в этой игре. Чтобы поучаствовать, нажми /start",
+        )
+        .await
+        .unwrap();
+    }
+}
+
+#[async_trait]
+impl StateStrategy for ReceiveCityStrategy {
+    async fn handle(&self, _user: User, msg: Message, bot: SantaBot, _db: DatabaseHandler) {
+        bot.send_message(msg.chat.id, "Выберите город из меню.")
+            .await
+            .unwrap();
+    }
+}
+
+#[async_trait]
+impl StateStrategy for LeftStrategy {
+    async fn handle(&self, _user: User, msg: Message, bot: SantaBot, _db: DatabaseHandler) {
+        bot.send_message(
+            msg.chat.id,
+            "Ты вышел из игры. Если передумаешь, нажми /start",
+        )
+        .await
+        .unwrap();
+    }
+}
+
 #[async_trait]
 impl StateStrategy for FinishStrategy {
     async fn handle(&self, mut user: User, msg: Message, bot: SantaBot, db: DatabaseHandler) {
@@ -109,7 +173,7 @@ impl StateStrategy for FinishStrategy {
                 let inline_keyboard =
                     InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
                         "Изменить",
-                        CHANGE_WISH_CALLBACK,
+                        callback_data(CHANGE_WISH_CALLBACK, user.game_id, ""),
                     )]]);
 
                 let text = format!(
@@ -137,6 +201,7 @@ impl StateStrategy for ChangeWishListStrategy {
                 bot.send_message(msg.chat.id, "Список желаний успешно изменен!")
                     .await
                     .unwrap();
+                warn_over_budget(&bot, &db, &user, message_text).await;
                 user.wish_text = message_text.to_string();
                 user.state = Option::from(State::Finish);
                 db.save_user(user).await;
@@ -164,23 +229,11 @@ impl StateStrategy for ChildChatStrategy {
                 user.state = Option::from(State::Distributed);
                 db.save_user(user).await;
             }
-            Some(message_text) => {
-                let child_id = user.child.unwrap();
-                let message = types::Message {
-                    santa_id: user.id,
-                    child_id,
-                    message: format!("<b>$santa: </b>\n{}", message_text),
-                    create_date: DateTimeWithTimeZone::from(Utc::now()),
-                };
-                db.save_message(message).await;
-                bot.send_message(
-                    ChatId(child_id),
-                    format!("У вас новое сообщение от Санты:\n{}", message_text),
-                )
-                .await
-                .unwrap();
+            _ => {
+                relay::relay(&bot, &db, &msg, &user, true, msg.reply_to_message().map(|reply| reply.id.0))
+                    .await
+                    .unwrap();
             }
-            None => {}
         }
     }
 }
@@ -203,24 +256,11 @@ impl StateStrategy for SantaChatStrategy {
                 user.state = Option::from(State::Distributed);
                 db.save_user(user).await;
             }
-            Some(message_text) => {
-                let santa_id = user.santa.unwrap();
-                let message = types::Message {
-                    child_id: user.id,
-                    santa_id,
-                    message: format!("<b>$child: </b>\n{}", message_text),
-                    create_date: DateTimeWithTimeZone::from(Utc::now()),
-                };
-                db.save_message(message).await;
-
-                bot.send_message(
-                    ChatId(santa_id),
-                    format!("У вас новое сообщение от подопечного:\n{}", message_text),
-                )
-                .await
-                .unwrap();
+            _ => {
+                relay::relay(&bot, &db, &msg, &user, false, msg.reply_to_message().map(|reply| reply.id.0))
+                    .await
+                    .unwrap();
             }
-            None => {}
         }
     }
 }
@@ -230,16 +270,25 @@ impl StateStrategy for ReceiveWishStrategy {
     async fn handle(&self, mut user: User, msg: Message, bot: SantaBot, db: DatabaseHandler) {
         match msg.text() {
             Some(wish) => {
-                let inline_keyboard = InlineKeyboardMarkup::new([
-                    [InlineKeyboardButton::callback(
-                        MOSCOW_CITY,
-                        CITY_CALLBACK_MSK,
-                    )],
+                user.wish_text = wish.parse().unwrap();
+                warn_over_budget(&bot, &db, &user, wish).await;
+                let groups = db.get_groups(user.game_id).await;
+                if groups.is_empty() {
+                    bot.send_message(msg.chat.id, "Спасибо, внучок, дедушка всё записал.")
+                        .await
+                        .unwrap();
+                    send_wait_distribution(&bot, msg.chat.id).await.unwrap();
+                    user.state = Option::from(State::Finish);
+                    db.save_user(user).await;
+                    return;
+                }
+
+                let inline_keyboard = InlineKeyboardMarkup::new(groups.iter().map(|group| {
                     [InlineKeyboardButton::callback(
-                        IZHEVSK_CITY,
-                        CITY_CALLBACK_IZH,
-                    )],
-                ]);
+                        group.name.clone(),
+                        callback_data(GROUP_CALLBACK_PREFIX, user.game_id, group.id),
+                    )]
+                }));
 
                 bot.send_message(
                     msg.chat.id,
@@ -250,7 +299,6 @@ impl StateStrategy for ReceiveWishStrategy {
                 .await
                 .unwrap();
 
-                user.wish_text = wish.parse().unwrap();
                 user.state = Option::from(State::ReceiveCity);
                 db.save_user(user).await;
             }
@@ -271,12 +319,17 @@ impl StateStrategy for ReceiveNameStrategy {
                 user.username = username.parse().unwrap();
                 user.state = Option::from(State::ReceiveWish);
 
-                bot.send_message(
-                    msg.chat.id,
-                    include_str!("templates/state_2_write_name_0.txt"),
-                )
-                .await
-                .unwrap();
+                let game = db.get_game(user.game_id).await.unwrap_or_default();
+                let text = match game.budget_text() {
+                    Some(budget) => {
+                        format!(
+                            include_str!("templates/state_2_write_name_0.txt"),
+                            html::escape(&budget)
+                        )
+                    }
+                    None => include_str!("templates/state_2_write_name_0_unlimited.txt").to_string(),
+                };
+                bot.send_message(msg.chat.id, text).await.unwrap();
 
                 let url_state_2 =
                     "https://www.sunhome.ru/i/cards/198/elka-animacionnaya-otkritka.orig.gif";
@@ -294,7 +347,7 @@ impl StateStrategy for ReceiveNameStrategy {
                 )
                 .await
                 .unwrap();
-                db.find_user(2).await;
+                db.find_user(user.game_id, 2).await;
                 db.save_user(user).await;
             }
             None => {
@@ -305,3 +358,22 @@ impl StateStrategy for ReceiveNameStrategy {
         }
     }
 }
+
+/// Предупреждает, если в списке желаний есть подарок дороже бюджета игры
+async fn warn_over_budget(bot: &SantaBot, db: &DatabaseHandler, user: &User, wish: &str) {
+    let game = db.get_game(user.game_id).await.unwrap_or_default();
+    let price = budget::max_price(wish, &game.currency);
+    if let (Some(budget), Some(price)) = (game.budget, price) {
+        if price > budget {
+            let text = format!(
+                "Ой-ой, {} {} дедушке не по карману: бюджет подарка — {}. \
+                 Список желаний можно поменять кнопкой «{}»",
+                price,
+                html::escape(&game.currency),
+                html::escape(&game.budget_text().unwrap_or_default()),
+                CHANGE_WISH_LIST
+            );
+            bot.send_message(ChatId(user.chat_id), text).await.unwrap();
+        }
+    }
+}
@@ -2,13 +2,19 @@ use std::str::FromStr;
 
 use chrono::Utc;
 use sea_orm::prelude::DateTimeWithTimeZone;
+use strum_macros::{Display, EnumString};
 
 use crate::bot::State;
 use crate::db::schema::user::Model;
+use crate::db::schema::{
+    assignment_history, chat_message, draw, exclusion, game, game_admin, game_group, schedule,
+};
+use crate::distribution::{DistributionMode, DrawInput};
 
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct User {
     pub id: i64,
+    pub game_id: i64,
     pub chat_id: i64,
     pub child: Option<i64>,
     pub santa: Option<i64>,
@@ -16,18 +22,325 @@ pub struct User {
     pub username: String,
     pub wish_text: String,
     pub city: String,
+    pub group_id: Option<i64>,
     pub state: Option<State>,
     pub create_date: DateTimeWithTimeZone,
 }
 
+/// Одно сообщение переписки Санты и подопечного. `sender_message_id` и `recipient_message_id` —
+/// id сообщения в чате отправителя и его копии в чате получателя. Перед копией вложения
+/// получатель видит отдельный заголовок, его id — `header_message_id`.
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct Message {
+    pub id: i64,
+    pub game_id: i64,
     pub santa_id: i64,
     pub child_id: i64,
-    pub message: String,
+    pub from_santa: bool,
+    pub kind: MessageKind,
+    /// Текст сообщения или подпись вложения
+    pub text: Option<String>,
+    pub file_id: Option<String>,
+    pub sender_message_id: Option<i32>,
+    pub recipient_message_id: Option<i32>,
+    pub header_message_id: Option<i32>,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+impl Message {
+    pub fn to_message(message: chat_message::Model) -> Option<Message> {
+        Some(Message {
+            id: message.id,
+            game_id: message.game_id,
+            santa_id: message.santa_id,
+            child_id: message.child_id,
+            from_santa: message.from_santa,
+            kind: MessageKind::from_str(message.kind.as_str()).ok()?,
+            text: message.text,
+            file_id: message.file_id,
+            sender_message_id: message.sender_message_id,
+            recipient_message_id: message.recipient_message_id,
+            header_message_id: message.header_message_id,
+            create_date: message.create_date,
+        })
+    }
+}
+
+/// Тип сообщения в переписке Санты и подопечного
+#[derive(Clone, Copy, Debug, Default, Display, EnumString, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum MessageKind {
+    #[default]
+    Text,
+    Photo,
+    Sticker,
+    Voice,
+    Document,
+    Video,
+    Audio,
+    VideoNote,
+    Animation,
+}
+
+impl MessageKind {
+    /// Как вложение выглядит в текстовой истории переписки
+    pub fn label(&self) -> &'static str {
+        match self {
+            MessageKind::Text => "",
+            MessageKind::Photo => "📷 Фото",
+            MessageKind::Sticker => "Стикер",
+            MessageKind::Voice => "🎤 Голосовое сообщение",
+            MessageKind::Document => "📎 Файл",
+            MessageKind::Video => "🎬 Видео",
+            MessageKind::Audio => "🎵 Аудио",
+            MessageKind::VideoNote => "📹 Видеосообщение",
+            MessageKind::Animation => "GIF",
+        }
+    }
+}
+
+/// Этап игры
+#[derive(Clone, Debug, Default, Display, EnumString, PartialEq)]
+pub enum GameState {
+    /// Идет регистрация, подопечные еще не распределены
+    #[default]
+    Registration,
+    Distributed,
+}
+
+/// Игра (отдельный обмен подарками) со своими участниками, организаторами и распределением
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Game {
+    pub id: i64,
+    pub name: String,
+    pub state: GameState,
+    pub join_code: Option<String>,
+    pub join_code_expires: Option<DateTimeWithTimeZone>,
+    /// Предельная стоимость подарка; `None` — без ограничения
+    pub budget: Option<i64>,
+    pub currency: String,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+impl Game {
+    pub fn to_game(game: game::Model) -> Game {
+        Game {
+            id: game.id,
+            name: game.name,
+            state: GameState::from_str(game.state.as_str()).unwrap_or_default(),
+            join_code: game.join_code,
+            join_code_expires: game.join_code_expires,
+            budget: game.budget,
+            currency: game.currency,
+            create_date: game.create_date,
+        }
+    }
+
+    /// Ссылка-приглашение еще действует
+    pub fn is_join_code_active(&self) -> bool {
+        match self.join_code_expires {
+            Some(expires) => expires > Utc::now(),
+            None => true,
+        }
+    }
+
+    /// Бюджет для шаблонов, например «2000 рублей»
+    pub fn budget_text(&self) -> Option<String> {
+        self.budget
+            .map(|budget| format!("{} {}", budget, self.currency))
+    }
+}
+
+/// Роль организатора: владелец (создатель игры) может назначать и снимать администраторов
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq)]
+pub enum AdminRole {
+    Owner,
+    Admin,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameAdmin {
+    pub game_id: i64,
+    pub user_id: i64,
+    pub role: AdminRole,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+impl GameAdmin {
+    pub fn to_admin(admin: game_admin::Model) -> GameAdmin {
+        GameAdmin {
+            game_id: admin.game_id,
+            user_id: admin.user_id,
+            role: AdminRole::from_str(admin.role.as_str()).unwrap_or(AdminRole::Admin),
+            create_date: admin.create_date,
+        }
+    }
+}
+
+/// Группа участников (например, город): Санта и подопечный выбираются из разных групп
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Group {
+    pub id: i64,
+    pub game_id: i64,
+    pub name: String,
     pub create_date: DateTimeWithTimeZone,
 }
 
+impl Group {
+    pub fn to_group(group: game_group::Model) -> Group {
+        Group {
+            id: group.id,
+            game_id: group.game_id,
+            name: group.name,
+            create_date: group.create_date,
+        }
+    }
+}
+
+/// Действие, которое бот выполняет по расписанию, название используется в `/schedule`
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, PartialOrd, Ord)]
+#[strum(serialize_all = "snake_case")]
+pub enum ScheduledAction {
+    /// Закрыть регистрацию: новые участники больше не принимаются
+    CloseRegistration,
+    Distribute,
+    Notify,
+    Reveal,
+}
+
+/// Запланированное действие игры; `done_at` заполняется после выполнения,
+/// поэтому после перезапуска бот выполняет только пропущенные действия
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    pub id: i64,
+    pub game_id: i64,
+    pub action: ScheduledAction,
+    /// Аргументы команды, например режим распределения
+    pub args: String,
+    pub run_at: DateTimeWithTimeZone,
+    pub done_at: Option<DateTimeWithTimeZone>,
+    /// Сколько раз действие уже завершилось ошибкой
+    pub attempts: i32,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+impl Schedule {
+    pub fn to_schedule(schedule: schedule::Model) -> Option<Schedule> {
+        Some(Schedule {
+            id: schedule.id,
+            game_id: schedule.game_id,
+            action: ScheduledAction::from_str(schedule.action.as_str()).ok()?,
+            args: schedule.args,
+            run_at: schedule.run_at,
+            done_at: schedule.done_at,
+            attempts: schedule.attempts,
+            create_date: schedule.create_date,
+        })
+    }
+}
+
+/// Запрет на пару: `santa_id` не может дарить подарок `child_id` (и наоборот, если `symmetric`)
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Exclusion {
+    pub id: i64,
+    pub game_id: i64,
+    pub santa_id: i64,
+    pub child_id: i64,
+    pub symmetric: bool,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+impl Exclusion {
+    pub fn to_exclusions(exclusions: Vec<exclusion::Model>) -> Vec<Exclusion> {
+        exclusions
+            .into_iter()
+            .map(|exclusion| Exclusion {
+                id: exclusion.id,
+                game_id: exclusion.game_id,
+                santa_id: exclusion.santa_id,
+                child_id: exclusion.child_id,
+                symmetric: exclusion.symmetric,
+                create_date: exclusion.create_date,
+            })
+            .collect()
+    }
+}
+
+/// Пара Санта -> подопечный из истории распределений.
+/// `previous_state` — состояние Санты до распределения, чтобы его можно было отменить.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Assignment {
+    pub season: i32,
+    pub santa_id: i64,
+    pub child_id: i64,
+    pub previous_state: Option<State>,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+impl Assignment {
+    pub fn to_assignments(assignments: Vec<assignment_history::Model>) -> Vec<Assignment> {
+        assignments
+            .into_iter()
+            .map(|assignment| Assignment {
+                season: assignment.season,
+                santa_id: assignment.santa_id,
+                child_id: assignment.child_id,
+                previous_state: assignment
+                    .previous_state
+                    .and_then(|state| State::from_str(state.as_str()).ok()),
+                create_date: assignment.create_date,
+            })
+            .collect()
+    }
+}
+
+/// Распределение: seed и хэш результата позволяют воспроизвести и проверить его.
+/// До распределения участникам публикуется только `commitment` (хэш seed),
+/// а сам seed раскрывается после праздника (`revealed`). `participants` — состав
+/// на момент распределения, он не меняется при добавлении опоздавших и выбывших.
+/// `input` — группы, запреты и история, с которыми оно проведено; у распределений,
+/// сохраненных до его появления, `None`.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Draw {
+    pub id: i64,
+    pub game_id: i64,
+    pub season: i32,
+    pub mode: DistributionMode,
+    pub history_seasons: usize,
+    pub seed: String,
+    pub commitment: String,
+    pub assignment_hash: Option<String>,
+    pub revealed: bool,
+    pub participants: Vec<i64>,
+    pub input: Option<DrawInput>,
+    pub create_date: DateTimeWithTimeZone,
+}
+
+impl Draw {
+    pub fn to_draw(draw: draw::Model) -> Draw {
+        Draw {
+            id: draw.id,
+            game_id: draw.game_id,
+            season: draw.season,
+            mode: DistributionMode::from_str(draw.mode.as_str()).unwrap_or_default(),
+            history_seasons: draw.history_seasons as usize,
+            seed: draw.seed,
+            commitment: draw.commitment,
+            assignment_hash: draw.assignment_hash,
+            revealed: draw.revealed,
+            participants: draw
+                .participants
+                .split(',')
+                .filter_map(|id| id.parse().ok())
+                .collect(),
+            input: draw
+                .input
+                .and_then(|input| DrawInput::from_str(input.as_str()).ok()),
+            create_date: draw.create_date,
+        }
+    }
+}
+
 impl User {
     pub fn to_users(users: Vec<Model>) -> Vec<User> {
         users.into_iter().map(|user| User::to_user(user)).collect()
@@ -35,6 +348,7 @@ impl User {
     pub fn to_user(user: Model) -> User {
         User {
             id: user.id,
+            game_id: user.game_id,
             chat_id: user.chat_id,
             child: user.child,
             santa: user.santa,
@@ -42,20 +356,42 @@ impl User {
             username: user.username,
             wish_text: user.wish_text,
             city: user.city,
+            group_id: user.group_id,
             state: State::from_str(user.state.as_str()).ok(),
             create_date: user.create_date,
         }
     }
-    pub fn default_user(chat_id: i64, nickname: String) -> Option<User> {
+    pub fn default_user(game_id: i64, chat_id: i64, nickname: String) -> Option<User> {
         let create_date = DateTimeWithTimeZone::from(Utc::now());
         Option::from(User {
             id: chat_id,
+            game_id,
             nickname,
             chat_id,
             create_date,
             ..Default::default()
         })
     }
+    /// Пользователь прошел регистрацию (ввел имя, пожелания и город) и не вышел из игры
+    pub fn is_registered(&self) -> bool {
+        !matches!(
+            self.state,
+            None | Some(State::Start)
+                | Some(State::ReceiveName)
+                | Some(State::ReceiveWish)
+                | Some(State::ReceiveCity)
+                | Some(State::Left)
+        )
+    }
+
+    /// Пользователь уже получил уведомление о своем подопечном
+    pub fn is_notified(&self) -> bool {
+        matches!(
+            self.state,
+            Some(State::Distributed) | Some(State::ChildChat) | Some(State::SantaChat)
+        )
+    }
+
     pub fn set_santa(&mut self, santa_id: i64) {
         self.santa = Option::from(santa_id);
     }
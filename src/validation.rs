@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::types::User;
+
+/// Нарушение согласованности пар `santa`/`child` в таблице `user`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    SelfAssigned { user: String },
+    MissingChild { user: String },
+    MissingSanta { user: String },
+    UnknownUser { user: String, id: i64 },
+    Asymmetric { santa: String, child: String },
+    NotParticipant { user: String },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::SelfAssigned { user } => write!(f, "{} сам себе Санта", user),
+            Violation::MissingChild { user } => write!(f, "{} остался без подопечного", user),
+            Violation::MissingSanta { user } => write!(f, "{} остался без Санты", user),
+            Violation::UnknownUser { user, id } => {
+                write!(f, "{} в паре с {}, которого нет среди участников", user, id)
+            }
+            Violation::Asymmetric { santa, child } => write!(
+                f,
+                "{} дарит подарок {}, но у {} записан другой Санта",
+                santa, child, child
+            ),
+            Violation::NotParticipant { user } => {
+                write!(f, "{} не участвует в игре, но состоит в паре", user)
+            }
+        }
+    }
+}
+
+/// Проверяет, что у каждого участника ровно один Санта и один подопечный, никто не дарит
+/// подарок сам себе, а указатели `santa` и `child` согласованы в обе стороны.
+/// Пока никто не распределен, отсутствие пар нарушением не считается.
+pub fn validate(users: &[User]) -> Vec<Violation> {
+    let by_id: HashMap<i64, &User> = users
+        .iter()
+        .filter(|user| user.is_registered())
+        .map(|user| (user.id, user))
+        .collect();
+    let distributed = by_id.values().any(|user| user.child.is_some());
+    let mut users: Vec<&User> = users.iter().collect();
+    users.sort_by_key(|user| user.id);
+
+    let mut violations = vec![];
+    for user in users {
+        if !user.is_registered() {
+            if user.santa.is_some() || user.child.is_some() {
+                violations.push(Violation::NotParticipant { user: label(user) });
+            }
+            continue;
+        }
+        if user.child == Some(user.id) || user.santa == Some(user.id) {
+            violations.push(Violation::SelfAssigned { user: label(user) });
+            continue;
+        }
+        match user.child {
+            None if distributed => {
+                violations.push(Violation::MissingChild { user: label(user) });
+            }
+            None => {}
+            Some(child_id) => match by_id.get(&child_id) {
+                None => violations.push(Violation::UnknownUser {
+                    user: label(user),
+                    id: child_id,
+                }),
+                Some(child) if child.santa != Some(user.id) => {
+                    push_unique(&mut violations, asymmetric(user, child));
+                }
+                Some(_) => {}
+            },
+        }
+        match user.santa {
+            None if distributed => {
+                violations.push(Violation::MissingSanta { user: label(user) });
+            }
+            None => {}
+            Some(santa_id) => match by_id.get(&santa_id) {
+                None => violations.push(Violation::UnknownUser {
+                    user: label(user),
+                    id: santa_id,
+                }),
+                Some(santa) if santa.child != Some(user.id) => {
+                    push_unique(&mut violations, asymmetric(santa, user));
+                }
+                Some(_) => {}
+            },
+        }
+    }
+    violations
+}
+
+fn asymmetric(santa: &User, child: &User) -> Violation {
+    Violation::Asymmetric {
+        santa: label(santa),
+        child: label(child),
+    }
+}
+
+fn push_unique(violations: &mut Vec<Violation>, violation: Violation) {
+    if !violations.contains(&violation) {
+        violations.push(violation);
+    }
+}
+
+fn label(user: &User) -> String {
+    format!("{} ({})", user.username, user.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::State;
+
+    fn user(id: i64, santa: Option<i64>, child: Option<i64>) -> User {
+        User {
+            id,
+            chat_id: id,
+            username: format!("u{}", id),
+            santa,
+            child,
+            state: Some(State::Distributed),
+            ..Default::default()
+        }
+    }
+
+    fn cycle() -> Vec<User> {
+        vec![
+            user(1, Some(3), Some(2)),
+            user(2, Some(1), Some(3)),
+            user(3, Some(2), Some(1)),
+        ]
+    }
+
+    #[test]
+    fn accepts_consistent_cycle() {
+        assert_eq!(validate(&cycle()), vec![]);
+    }
+
+    #[test]
+    fn finds_self_assignment() {
+        let users = vec![user(1, Some(1), Some(1))];
+        assert_eq!(
+            validate(&users),
+            vec![Violation::SelfAssigned {
+                user: "u1 (1)".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn finds_duplicate_child() {
+        let mut users = cycle();
+        // И 1, и 3 дарят подарок 2, а у 2 записан только один Санта
+        users[2].child = Some(2);
+        assert!(validate(&users).contains(&Violation::Asymmetric {
+            santa: "u3 (3)".to_string(),
+            child: "u2 (2)".to_string(),
+        }));
+    }
+
+    #[test]
+    fn finds_broken_back_pointer() {
+        let mut users = cycle();
+        users[1].santa = Some(3);
+        let violations = validate(&users);
+        assert!(violations.contains(&Violation::Asymmetric {
+            santa: "u1 (1)".to_string(),
+            child: "u2 (2)".to_string(),
+        }));
+        assert!(violations.contains(&Violation::Asymmetric {
+            santa: "u3 (3)".to_string(),
+            child: "u2 (2)".to_string(),
+        }));
+    }
+
+    #[test]
+    fn finds_left_participant_still_assigned() {
+        let mut users = cycle();
+        users[2].state = Some(State::Left);
+        let violations = validate(&users);
+        assert!(violations.contains(&Violation::NotParticipant {
+            user: "u3 (3)".to_string()
+        }));
+        assert!(violations.contains(&Violation::UnknownUser {
+            user: "u1 (1)".to_string(),
+            id: 3,
+        }));
+    }
+
+    #[test]
+    fn finds_unassigned_participant() {
+        let mut users = cycle();
+        users.push(user(4, None, None));
+        assert_eq!(
+            validate(&users),
+            vec![
+                Violation::MissingChild {
+                    user: "u4 (4)".to_string()
+                },
+                Violation::MissingSanta {
+                    user: "u4 (4)".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_missing_pairs_before_distribution() {
+        let users = vec![user(1, None, None), user(2, None, None)];
+        assert_eq!(validate(&users), vec![]);
+    }
+}
@@ -4,7 +4,8 @@ pub const BOT_USERNAME_MESSAGE: &str = "Test client";
 
 pub const BOT_WISH_MESSAGE: &str = "Велик";
 
-pub const CITY_CALLBACK_MSK: &str = "__msk_callback";
+/// Москва — первая группа игры по умолчанию, ее создает миграция
+pub const CITY_CALLBACK_MSK: &str = "__group_callback:1:1";
 
 pub const REPEAT_START_MESSAGE: &str = "Хитрец! Больше одного подарка не положено";
 